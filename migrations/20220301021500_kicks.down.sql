@@ -0,0 +1,6 @@
+DELETE FROM punishments WHERE punishment_type = 'kick';
+
+ALTER TYPE punishment_type RENAME TO punishment_type_old;
+CREATE TYPE punishment_type AS ENUM ('dunce', 'ban');
+ALTER TABLE punishments ALTER COLUMN punishment_type TYPE punishment_type USING punishment_type::text::punishment_type;
+DROP TYPE punishment_type_old;
@@ -0,0 +1,3 @@
+ALTER TABLE punishments DROP COLUMN moderator_id;
+ALTER TABLE punishments DROP COLUMN reason;
+ALTER TABLE punishments DROP COLUMN created_at;
@@ -0,0 +1,3 @@
+ALTER TABLE punishments ADD COLUMN moderator_id bigint;
+ALTER TABLE punishments ADD COLUMN reason text;
+ALTER TABLE punishments ADD COLUMN created_at timestamp NOT NULL DEFAULT (now() AT TIME ZONE 'utc');
@@ -0,0 +1,9 @@
+DELETE FROM punishment_removed_roles WHERE punishment_id IN (SELECT id FROM punishments WHERE resolved_at IS NOT NULL);
+DELETE FROM punishments WHERE resolved_at IS NOT NULL;
+
+ALTER TABLE punishments DROP COLUMN resolved_reason;
+ALTER TABLE punishments DROP COLUMN resolved_by;
+ALTER TABLE punishments DROP COLUMN resolved_at;
+
+ALTER TABLE punishments DROP CONSTRAINT punishments_server_case_number_key;
+ALTER TABLE punishments DROP COLUMN case_number;
@@ -0,0 +1,12 @@
+ALTER TABLE punishments ADD COLUMN case_number bigint;
+UPDATE punishments SET case_number = numbered.case_number
+    FROM (SELECT id, row_number() OVER (PARTITION BY server_id ORDER BY id) AS case_number FROM punishments) numbered
+    WHERE punishments.id = numbered.id;
+ALTER TABLE punishments ALTER COLUMN case_number SET NOT NULL;
+ALTER TABLE punishments ADD CONSTRAINT punishments_server_case_number_key UNIQUE (server_id, case_number);
+
+ALTER TABLE punishments ADD COLUMN resolved_at timestamp;
+ALTER TABLE punishments ADD COLUMN resolved_by bigint;
+ALTER TABLE punishments ADD COLUMN resolved_reason text;
+
+UPDATE punishments SET resolved_at = created_at WHERE punishment_type = 'kick';
@@ -0,0 +1 @@
+ALTER TABLE servers DROP COLUMN mod_log_channel_id;
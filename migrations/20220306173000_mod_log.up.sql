@@ -0,0 +1 @@
+ALTER TABLE servers ADD COLUMN mod_log_channel_id bigint;
@@ -0,0 +1,2 @@
+DROP TABLE warning_escalations;
+DROP TABLE warnings;
@@ -0,0 +1,17 @@
+CREATE TABLE warnings (
+    id bigserial PRIMARY KEY,
+    server_id bigint NOT NULL,
+    user_id bigint NOT NULL,
+    moderator_id bigint NOT NULL,
+    reason text NOT NULL,
+    created_at timestamp NOT NULL
+);
+
+CREATE TABLE warning_escalations (
+    id bigserial PRIMARY KEY,
+    server_id bigint NOT NULL,
+    warning_count bigint NOT NULL,
+    period_days bigint NOT NULL,
+    punishment_type punishment_type NOT NULL,
+    duration_minutes bigint
+);
@@ -0,0 +1,9 @@
+DELETE FROM punishment_removed_roles WHERE punishment_id IN (SELECT id FROM punishments WHERE punishment_type = 'timeout');
+DELETE FROM punishments WHERE punishment_type = 'timeout';
+DELETE FROM warning_escalations WHERE punishment_type = 'timeout';
+
+ALTER TYPE punishment_type RENAME TO punishment_type_old;
+CREATE TYPE punishment_type AS ENUM ('dunce', 'ban', 'kick');
+ALTER TABLE punishments ALTER COLUMN punishment_type TYPE punishment_type USING punishment_type::text::punishment_type;
+ALTER TABLE warning_escalations ALTER COLUMN punishment_type TYPE punishment_type USING punishment_type::text::punishment_type;
+DROP TYPE punishment_type_old;
@@ -0,0 +1 @@
+ALTER TYPE punishment_type ADD VALUE 'timeout';
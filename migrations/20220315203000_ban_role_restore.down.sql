@@ -0,0 +1 @@
+ALTER TABLE servers DROP COLUMN ban_role_restore_hours;
@@ -0,0 +1 @@
+ALTER TABLE servers ADD COLUMN ban_role_restore_hours bigint NOT NULL DEFAULT 168;
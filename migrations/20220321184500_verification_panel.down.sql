@@ -0,0 +1,3 @@
+ALTER TABLE servers DROP COLUMN verification_button_label;
+ALTER TABLE servers DROP COLUMN verification_panel_text;
+ALTER TABLE servers DROP COLUMN verification_panel_title;
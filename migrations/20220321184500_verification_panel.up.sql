@@ -0,0 +1,3 @@
+ALTER TABLE servers ADD COLUMN verification_panel_title text;
+ALTER TABLE servers ADD COLUMN verification_panel_text text;
+ALTER TABLE servers ADD COLUMN verification_button_label text;
@@ -0,0 +1,5 @@
+DROP TABLE captcha_challenges;
+
+ALTER TABLE servers DROP COLUMN captcha_lockout_minutes;
+ALTER TABLE servers DROP COLUMN captcha_attempts;
+ALTER TABLE servers DROP COLUMN captcha_enabled;
@@ -0,0 +1,14 @@
+ALTER TABLE servers ADD COLUMN captcha_enabled boolean NOT NULL DEFAULT false;
+ALTER TABLE servers ADD COLUMN captcha_attempts bigint NOT NULL DEFAULT 3;
+ALTER TABLE servers ADD COLUMN captcha_lockout_minutes bigint NOT NULL DEFAULT 30;
+
+CREATE TABLE captcha_challenges (
+    server_id bigint NOT NULL,
+    user_id bigint NOT NULL,
+    answer text NOT NULL,
+    puzzle text NOT NULL,
+    input text NOT NULL DEFAULT '',
+    failed_attempts bigint NOT NULL DEFAULT 0,
+    locked_until timestamp,
+    PRIMARY KEY (server_id, user_id)
+);
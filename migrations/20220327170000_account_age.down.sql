@@ -0,0 +1,3 @@
+ALTER TABLE servers DROP COLUMN kick_account_age_hours;
+ALTER TABLE servers DROP COLUMN account_age_message;
+ALTER TABLE servers DROP COLUMN min_account_age_hours;
@@ -0,0 +1,3 @@
+ALTER TABLE servers ADD COLUMN min_account_age_hours bigint;
+ALTER TABLE servers ADD COLUMN account_age_message text;
+ALTER TABLE servers ADD COLUMN kick_account_age_hours bigint;
@@ -0,0 +1,4 @@
+DROP TABLE verification_requests;
+
+ALTER TABLE servers DROP COLUMN approval_required;
+ALTER TABLE servers DROP COLUMN approval_channel_id;
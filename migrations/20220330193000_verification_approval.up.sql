@@ -0,0 +1,10 @@
+ALTER TABLE servers ADD COLUMN approval_channel_id bigint;
+ALTER TABLE servers ADD COLUMN approval_required boolean NOT NULL DEFAULT false;
+
+CREATE TABLE verification_requests (
+    server_id bigint NOT NULL,
+    user_id bigint NOT NULL,
+    message_id bigint NOT NULL,
+    created_at timestamp NOT NULL DEFAULT (now() AT TIME ZONE 'utc'),
+    PRIMARY KEY (server_id, user_id)
+);
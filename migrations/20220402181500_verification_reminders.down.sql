@@ -0,0 +1,4 @@
+DROP TABLE verification_reminders;
+
+ALTER TABLE servers DROP COLUMN verification_invite_url;
+ALTER TABLE servers DROP COLUMN verification_reminder_percents;
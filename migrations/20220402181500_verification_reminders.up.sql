@@ -0,0 +1,10 @@
+ALTER TABLE servers ADD COLUMN verification_reminder_percents text;
+ALTER TABLE servers ADD COLUMN verification_invite_url text;
+
+CREATE TABLE verification_reminders (
+    server_id bigint NOT NULL,
+    user_id bigint NOT NULL,
+    joined_at timestamp NOT NULL,
+    last_percent bigint NOT NULL,
+    PRIMARY KEY (server_id, user_id)
+);
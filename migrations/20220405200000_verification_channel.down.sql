@@ -0,0 +1,2 @@
+ALTER TABLE servers DROP COLUMN verification_revoke_on_unreact;
+ALTER TABLE servers DROP COLUMN verification_channel_id;
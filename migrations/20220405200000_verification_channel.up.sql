@@ -0,0 +1,2 @@
+ALTER TABLE servers ADD COLUMN verification_channel_id bigint;
+ALTER TABLE servers ADD COLUMN verification_revoke_on_unreact boolean NOT NULL DEFAULT false;
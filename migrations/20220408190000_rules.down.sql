@@ -0,0 +1,5 @@
+DROP TABLE rules_answers;
+DROP TABLE rules_questions;
+
+ALTER TABLE servers DROP COLUMN rules_required;
+ALTER TABLE servers DROP COLUMN rules_text;
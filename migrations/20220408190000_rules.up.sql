@@ -0,0 +1,19 @@
+ALTER TABLE servers ADD COLUMN rules_text text;
+ALTER TABLE servers ADD COLUMN rules_required boolean NOT NULL DEFAULT false;
+
+CREATE TABLE rules_questions (
+    id bigserial PRIMARY KEY,
+    server_id bigint NOT NULL,
+    prompt text NOT NULL,
+    free_text boolean NOT NULL
+);
+
+CREATE TABLE rules_answers (
+    id bigserial PRIMARY KEY,
+    question_id bigint NOT NULL REFERENCES rules_questions (id) ON DELETE CASCADE,
+    server_id bigint NOT NULL,
+    user_id bigint NOT NULL,
+    answer text NOT NULL,
+    answered_at timestamp NOT NULL,
+    UNIQUE (question_id, user_id)
+);
@@ -0,0 +1,5 @@
+CREATE TABLE role_menus (
+    message_id bigint PRIMARY KEY,
+    channel_id bigint NOT NULL,
+    server_id bigint NOT NULL
+);
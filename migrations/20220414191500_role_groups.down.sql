@@ -0,0 +1,3 @@
+ALTER TABLE optional_roles DROP COLUMN group_id;
+
+DROP TABLE role_groups;
@@ -0,0 +1,10 @@
+CREATE TABLE role_groups (
+    id bigserial PRIMARY KEY,
+    server_id bigint NOT NULL,
+    name text NOT NULL,
+    min_values bigint NOT NULL DEFAULT 0,
+    max_values bigint NOT NULL,
+    UNIQUE (server_id, name)
+);
+
+ALTER TABLE optional_roles ADD COLUMN group_id bigint REFERENCES role_groups (id) ON DELETE SET NULL;
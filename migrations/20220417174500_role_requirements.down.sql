@@ -0,0 +1,2 @@
+ALTER TABLE optional_roles DROP COLUMN excluded_role_ids;
+ALTER TABLE optional_roles DROP COLUMN required_role_ids;
@@ -0,0 +1,2 @@
+ALTER TABLE optional_roles ADD COLUMN required_role_ids text;
+ALTER TABLE optional_roles ADD COLUMN excluded_role_ids text;
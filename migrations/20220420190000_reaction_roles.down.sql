@@ -0,0 +1,3 @@
+DROP TABLE reaction_roles;
+
+DROP TYPE reaction_role_mode;
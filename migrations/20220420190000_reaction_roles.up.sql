@@ -0,0 +1,12 @@
+CREATE TYPE reaction_role_mode AS ENUM ('toggle', 'add_only', 'unique');
+
+CREATE TABLE reaction_roles (
+    id bigserial PRIMARY KEY,
+    server_id bigint NOT NULL,
+    channel_id bigint NOT NULL,
+    message_id bigint NOT NULL,
+    emoji text NOT NULL,
+    role_id bigint NOT NULL,
+    mode reaction_role_mode NOT NULL DEFAULT 'toggle',
+    UNIQUE (message_id, emoji)
+);
@@ -0,0 +1,3 @@
+DROP TABLE temporary_roles;
+
+ALTER TABLE optional_roles DROP COLUMN duration_minutes;
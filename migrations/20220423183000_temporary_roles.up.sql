@@ -0,0 +1,9 @@
+ALTER TABLE optional_roles ADD COLUMN duration_minutes bigint;
+
+CREATE TABLE temporary_roles (
+    server_id bigint NOT NULL,
+    user_id bigint NOT NULL,
+    role_id bigint NOT NULL,
+    expires timestamp NOT NULL,
+    PRIMARY KEY (server_id, user_id, role_id)
+);
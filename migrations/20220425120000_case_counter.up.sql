@@ -0,0 +1,4 @@
+ALTER TABLE servers ADD COLUMN last_case_number bigint NOT NULL DEFAULT 0;
+UPDATE servers SET last_case_number = latest.case_number
+    FROM (SELECT server_id, MAX(case_number) AS case_number FROM punishments GROUP BY server_id) latest
+    WHERE servers.id = latest.server_id;
@@ -0,0 +1 @@
+ALTER TABLE warnings DROP COLUMN escalated_count;
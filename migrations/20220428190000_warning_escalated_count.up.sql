@@ -0,0 +1 @@
+ALTER TABLE warnings ADD COLUMN escalated_count bigint;
@@ -0,0 +1 @@
+ALTER TABLE punishments DROP COLUMN expiry_attempts;
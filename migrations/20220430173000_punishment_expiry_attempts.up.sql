@@ -0,0 +1 @@
+ALTER TABLE punishments ADD COLUMN expiry_attempts bigint NOT NULL DEFAULT 0;
@@ -0,0 +1,193 @@
+use chrono::Utc;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{DatabaseConnection, EntityTrait, ActiveModelTrait, QueryFilter, ColumnTrait};
+use serenity::builder::CreateComponents;
+use serenity::model::id::{ChannelId, GuildId, RoleId, UserId};
+use serenity::model::interactions::InteractionResponseType;
+use serenity::model::interactions::message_component::ButtonStyle;
+use serenity::model::prelude::InteractionApplicationCommandCallbackDataFlags;
+use serenity::model::prelude::message_component::MessageComponentInteraction;
+use serenity::prelude::{Context, Mentionable};
+use crate::error::RaincoatError;
+use crate::model::server;
+use crate::model::verification_request;
+use super::{punishments, verification};
+
+/// Posts an approval card for a user in the server's staff channel, returning the message to show the user.
+pub async fn queue_for_approval(db: &DatabaseConnection, ctx: &Context, server_model: &server::Model, user_id: UserId) -> Result<String, RaincoatError> {
+    let server_id = GuildId(server_model.id as u64);
+    let channel_id = server_model.approval_channel_id
+        .ok_or(RaincoatError { cause: "Verification approval is not enabled on this server.".to_string() })?;
+
+    let existing = verification_request::Entity::find_by_id((server_id.0 as i64, user_id.0 as i64)).one(db).await
+        .map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+    if existing.is_some() {
+        return Ok("Your verification request is already waiting for a moderator.".to_string());
+    }
+
+    let user = user_id.to_user(ctx).await
+        .map_err(|err| RaincoatError { cause: format!("Unable to fetch information about user: {}", err) })?;
+    let joined_at = ctx.cache.member(server_id, user_id).await
+        .and_then(|member| member.joined_at);
+
+    let card = ChannelId(channel_id as u64).send_message(&ctx.http, |m| {
+        m.embed(|e| {
+            e.title("Verification request")
+                .thumbnail(user.face())
+                .field("User", format!("{} ({})", user.tag(), user_id.mention()), false)
+                .field("Account created", format!("<t:{}:R>", user_id.created_at().timestamp()), true);
+            if let Some(joined_at) = joined_at {
+                e.field("Joined", format!("<t:{}:R>", joined_at.timestamp()), true);
+            }
+            e.timestamp(Utc::now())
+        })
+            .components(|c| {
+                c.create_action_row(|row| {
+                    row.create_button(|button| {
+                        button.custom_id(format!("approval:approve:{}", user_id.0))
+                            .label("Approve")
+                            .style(ButtonStyle::Success)
+                    })
+                        .create_button(|button| {
+                            button.custom_id(format!("approval:deny:{}", user_id.0))
+                                .label("Deny")
+                                .style(ButtonStyle::Secondary)
+                        })
+                        .create_button(|button| {
+                            button.custom_id(format!("approval:ban:{}", user_id.0))
+                                .label("Ban")
+                                .style(ButtonStyle::Danger)
+                        })
+                })
+            })
+            .allowed_mentions(|f| f.empty_parse())
+    }).await.map_err(|err| RaincoatError { cause: format!("Failed to post verification request: {}", err) })?;
+
+    verification_request::ActiveModel {
+        server_id: Set(server_id.0 as i64),
+        user_id: Set(user_id.0 as i64),
+        message_id: Set(card.id.0 as i64),
+        created_at: Set(Utc::now().naive_utc())
+    }.insert(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+    Ok("Your verification request has been sent to the moderators. You will be given access once it is approved.".to_string())
+}
+
+/// Drops the pending request of a user who left the server, marking their approval card as stale.
+pub async fn withdraw_request(db: &DatabaseConnection, ctx: &Context, server_id: GuildId, user_id: UserId) -> Result<(), RaincoatError> {
+    let request = match verification_request::Entity::find_by_id((server_id.0 as i64, user_id.0 as i64)).one(db).await
+        .map_err(|err| RaincoatError { cause: format!("{}", err) })? {
+        Some(request) => request,
+        None => return Ok(())
+    };
+
+    verification_request::Entity::delete_many()
+        .filter(verification_request::Column::ServerId.eq(server_id.0 as i64))
+        .filter(verification_request::Column::UserId.eq(user_id.0 as i64))
+        .exec(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+    let server_model = server::Entity::find_by_id(server_id.0 as i64).one(db).await
+        .map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+    if let Some(channel_id) = server_model.and_then(|server_model| server_model.approval_channel_id) {
+        ChannelId(channel_id as u64).edit_message(&ctx.http, request.message_id as u64, |m| {
+            m.content(format!("{} left the server before their request was handled.", user_id.mention()))
+                .components(|c| c)
+        }).await.map_err(|err| RaincoatError { cause: format!("Failed to update verification request: {}", err) })?;
+    }
+
+    Ok(())
+}
+
+pub async fn create_component_response(db: &DatabaseConnection, ctx: &Context, component: &MessageComponentInteraction) -> Result<(), RaincoatError> {
+    let server_id = component.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
+
+    let mut parts = component.data.custom_id.split(':').skip(1);
+    let action = parts.next()
+        .ok_or(RaincoatError { cause: "Malformed verification request button".to_string() })?;
+    let user_id: u64 = parts.next()
+        .and_then(|user_id| user_id.parse().ok())
+        .ok_or(RaincoatError { cause: "Malformed verification request button".to_string() })?;
+
+    let server_model = server::Entity::find_by_id(server_id.0 as i64).one(db).await
+        .map_err(|err| RaincoatError { cause: format!("{}", err) })?
+        .ok_or(RaincoatError { cause: "This server has not been set up.".to_string() })?;
+
+    let is_mod = component.member.as_ref()
+        .is_some_and(|member| member.roles.contains(&RoleId(server_model.mod_role_id as u64)));
+    if !is_mod {
+        return component.create_interaction_response(&ctx.http, |response| {
+            response.kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message.content("Only moderators can handle verification requests.")
+                        .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                })
+        }).await.map_err(|err| RaincoatError { cause: format!("Failed to respond to component: {}", err) });
+    }
+
+    let request = verification_request::Entity::find_by_id((server_id.0 as i64, user_id as i64)).one(db).await
+        .map_err(|err| RaincoatError { cause: format!("{}", err) })?
+        .ok_or(RaincoatError { cause: "This verification request has already been handled.".to_string() })?;
+
+    // Claim the request first, so two moderators pressing buttons at once don't both act on it
+    let claimed = verification_request::Entity::delete_many()
+        .filter(verification_request::Column::ServerId.eq(server_id.0 as i64))
+        .filter(verification_request::Column::UserId.eq(user_id as i64))
+        .exec(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+    if claimed.rows_affected == 0 {
+        return Err(RaincoatError { cause: "This verification request has already been handled.".to_string() });
+    }
+
+    let result = handle_request(db, ctx, component, &server_model, action, user_id).await;
+
+    // Put the request back if the action failed, so the card can be used again
+    let result = match result {
+        Ok(result) => result,
+        Err(err) => {
+            verification_request::ActiveModel {
+                server_id: Set(request.server_id),
+                user_id: Set(request.user_id),
+                message_id: Set(request.message_id),
+                created_at: Set(request.created_at)
+            }.insert(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+            return Err(err);
+        }
+    };
+
+    component.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::UpdateMessage)
+            .interaction_response_data(|message| {
+                message.content(result)
+                    .set_components(CreateComponents::default())
+                    .allowed_mentions(|f| f.empty_parse())
+            })
+    }).await.map_err(|err| RaincoatError { cause: format!("Failed to respond to component: {}", err) })
+}
+
+/// Carries out a moderator's decision on a verification request, returning the text to replace the card with.
+async fn handle_request(db: &DatabaseConnection, ctx: &Context, component: &MessageComponentInteraction, server_model: &server::Model, action: &str, user_id: u64) -> Result<String, RaincoatError> {
+    let server_id = GuildId(server_model.id as u64);
+    let moderator_id = component.user.id;
+
+    Ok(match action {
+        "approve" => {
+            let verified_role_id = server_model.verified_role_id
+                .ok_or(RaincoatError { cause: "Verification is not enabled on this server.".to_string() })?;
+            verification::grant_verified_role(ctx, server_id, UserId(user_id), RoleId(verified_role_id as u64)).await?;
+
+            format!("Approved by {}", moderator_id.mention())
+        }
+        "deny" => {
+            let punishment_model = punishments::kick_user(db, ctx, server_id, user_id, Some(moderator_id.0),
+                                                          "Verification request denied".to_string()).await?;
+
+            format!("Denied and kicked by {} (case #{})", moderator_id.mention(), punishment_model.case_number)
+        }
+        "ban" => {
+            let punishment_model = punishments::ban_user(db, ctx, server_id, user_id, Some(moderator_id.0),
+                                                         Some("Verification request denied".to_string()), None, 0).await?;
+
+            format!("Banned by {} (case #{})", moderator_id.mention(), punishment_model.case_number)
+        }
+        unknown => return Err(RaincoatError { cause: format!("Unknown verification request action: {}", unknown) })
+    })
+}
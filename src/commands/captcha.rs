@@ -0,0 +1,306 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use rand::Rng;
+use rand::seq::SliceRandom;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{DatabaseConnection, EntityTrait, ActiveModelTrait, ModelTrait};
+use serenity::builder::CreateComponents;
+use serenity::model::id::{GuildId, UserId};
+use serenity::model::interactions::InteractionResponseType;
+use serenity::model::interactions::message_component::ButtonStyle;
+use serenity::model::prelude::message_component::MessageComponentInteraction;
+use serenity::prelude::Context;
+use crate::error::RaincoatError;
+use crate::model::captcha_challenge;
+use crate::model::server;
+use super::verification;
+use super::verification::VerificationOutcome;
+
+const CODE_LENGTH: usize = 5;
+const CANVAS_HEIGHT: usize = 7;
+
+const FILL_CHARS: [char; 6] = ['#', '@', '%', '&', '$', 'X'];
+const NOISE_CHARS: [char; 5] = ['.', ':', '\'', '`', ','];
+
+const GLYPHS: [[&str; 5]; 10] = [
+    ["###", "# #", "# #", "# #", "###"],
+    [" # ", "## ", " # ", " # ", "###"],
+    ["###", "  #", "###", "#  ", "###"],
+    ["###", "  #", " ##", "  #", "###"],
+    ["# #", "# #", "###", "  #", "  #"],
+    ["###", "#  ", "###", "  #", "###"],
+    ["###", "#  ", "###", "# #", "###"],
+    ["###", "  #", " # ", "#  ", "#  "],
+    ["###", "# #", "###", "# #", "###"],
+    ["###", "# #", "###", "  #", "###"]
+];
+
+fn generate_answer() -> String {
+    let mut rng = rand::thread_rng();
+    (0..CODE_LENGTH)
+        .map(|_| char::from(b'0' + rng.gen_range(0..10)))
+        .collect()
+}
+
+/// Draws the code as ASCII art, jittering each digit and sprinkling noise around it so it can't be read as plain text.
+fn render_puzzle(answer: &str) -> String {
+    let mut rng = rand::thread_rng();
+    let mut canvas: Vec<Vec<char>> = vec![Vec::new(); CANVAS_HEIGHT];
+
+    for digit in answer.chars() {
+        let glyph = &GLYPHS[digit.to_digit(10).unwrap_or(0) as usize];
+        let fill = *FILL_CHARS.choose(&mut rng).unwrap_or(&'#');
+        let offset = rng.gen_range(0..=CANVAS_HEIGHT - glyph.len());
+        let gap = rng.gen_range(1..=2);
+
+        for (row_index, row) in canvas.iter_mut().enumerate() {
+            row.extend(std::iter::repeat_n(' ', gap));
+            match row_index.checked_sub(offset).and_then(|glyph_row| glyph.get(glyph_row)) {
+                Some(glyph_row) => row.extend(glyph_row.chars().map(|c| if c == ' ' { ' ' } else { fill })),
+                None => row.extend(std::iter::repeat_n(' ', 3))
+            }
+        }
+    }
+
+    canvas.into_iter()
+        .map(|row| row.into_iter()
+            .map(|c| if c == ' ' && rng.gen_bool(0.1) { *NOISE_CHARS.choose(&mut rng).unwrap_or(&'.') } else { c })
+            .collect::<String>())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Applies a keypad button to what the user has entered so far, ignoring digits past the length of the code.
+fn enter_key(input: &str, key: &str) -> String {
+    let mut input = input.to_string();
+    if key == "back" {
+        input.pop();
+    } else if input.len() < CODE_LENGTH {
+        input.push_str(key);
+    }
+    input
+}
+
+fn lockout_message(locked_until: NaiveDateTime) -> String {
+    format!("You have made too many incorrect attempts. You can try again <t:{}:R>.", locked_until.timestamp())
+}
+
+fn challenge_message(challenge: &captcha_challenge::Model, notice: Option<&str>) -> (String, CreateComponents) {
+    let entered: String = challenge.input.chars()
+        .chain(std::iter::repeat('_'))
+        .take(CODE_LENGTH)
+        .map(|c| c.to_string())
+        .collect::<Vec<String>>()
+        .join(" ");
+
+    let mut content = String::new();
+    if let Some(notice) = notice {
+        content.push_str(notice);
+        content.push('\n');
+    }
+    content.push_str(&format!("Enter the code below to verify.\n```\n{}\n```\nEntered: `{}`", challenge.puzzle, entered));
+
+    let server_id = challenge.server_id;
+    let mut components = CreateComponents::default();
+    for keys in [["1", "2", "3", "4", "5"], ["6", "7", "8", "9", "0"]] {
+        components.create_action_row(|row| {
+            for key in keys {
+                row.create_button(|button| {
+                    button.custom_id(format!("captcha:{}:{}", server_id, key))
+                        .label(key)
+                        .style(ButtonStyle::Secondary)
+                });
+            }
+            row
+        });
+    }
+    components.create_action_row(|row| {
+        row.create_button(|button| {
+            button.custom_id(format!("captcha:{}:back", server_id))
+                .label("Backspace")
+                .style(ButtonStyle::Secondary)
+        })
+            .create_button(|button| {
+                button.custom_id(format!("captcha:{}:submit", server_id))
+                    .label("Submit")
+                    .style(ButtonStyle::Success)
+            })
+            .create_button(|button| {
+                button.custom_id(format!("captcha:{}:new", server_id))
+                    .label("New code")
+                    .style(ButtonStyle::Primary)
+            })
+    });
+
+    (content, components)
+}
+
+/// Starts a new captcha for a user, or explains when they can try again if they are locked out.
+///
+/// Failed attempts carry over between challenges, so asking for a new code doesn't get around the attempt limit.
+pub async fn start_challenge(db: &DatabaseConnection, server_id: GuildId, user_id: UserId) -> Result<(String, CreateComponents), RaincoatError> {
+    let existing = captcha_challenge::Entity::find_by_id((server_id.0 as i64, user_id.0 as i64)).one(db).await
+        .map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+    let answer = generate_answer();
+    let puzzle = render_puzzle(&answer);
+
+    let challenge = match existing {
+        Some(existing) => {
+            if let Some(locked_until) = existing.locked_until.filter(|locked_until| *locked_until > Utc::now().naive_utc()) {
+                return Ok((lockout_message(locked_until), CreateComponents::default()));
+            }
+
+            let mut challenge: captcha_challenge::ActiveModel = existing.into();
+            challenge.answer = Set(answer);
+            challenge.puzzle = Set(puzzle);
+            challenge.input = Set(String::new());
+            challenge.locked_until = Set(None);
+            challenge.update(db).await
+        }
+        None => {
+            captcha_challenge::ActiveModel {
+                server_id: Set(server_id.0 as i64),
+                user_id: Set(user_id.0 as i64),
+                answer: Set(answer),
+                puzzle: Set(puzzle),
+                input: Set(String::new()),
+                failed_attempts: Set(0),
+                locked_until: Set(None)
+            }.insert(db).await
+        }
+    }.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+    Ok(challenge_message(&challenge, None))
+}
+
+pub async fn create_component_response(db: &DatabaseConnection, ctx: &Context, component: &MessageComponentInteraction) -> Result<(), RaincoatError> {
+    // Challenges can be answered in DMs, so the server comes from the button rather than the interaction
+    let mut parts = component.data.custom_id.split(':').skip(1);
+    let server_id: u64 = parts.next()
+        .and_then(|server_id| server_id.parse().ok())
+        .ok_or(RaincoatError { cause: "Malformed captcha button".to_string() })?;
+    let key = parts.next()
+        .ok_or(RaincoatError { cause: "Malformed captcha button".to_string() })?;
+
+    let server_model = server::Entity::find_by_id(server_id as i64).one(db).await
+        .map_err(|err| RaincoatError { cause: format!("{}", err) })?
+        .ok_or(RaincoatError { cause: "This server has not been set up.".to_string() })?;
+
+    let challenge = captcha_challenge::Entity::find_by_id((server_id as i64, component.user.id.0 as i64)).one(db).await
+        .map_err(|err| RaincoatError { cause: format!("{}", err) })?
+        .ok_or(RaincoatError { cause: "This challenge has expired, please start verifying again.".to_string() })?;
+
+    let now = Utc::now().naive_utc();
+    let (content, components) = if let Some(locked_until) = challenge.locked_until.filter(|locked_until| *locked_until > now) {
+        (lockout_message(locked_until), CreateComponents::default())
+    } else {
+        match key {
+            "submit" if challenge.input == challenge.answer => {
+                challenge.delete(db).await
+                    .map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+                match verification::finish_verification(db, ctx, &server_model, component.user.id).await? {
+                    VerificationOutcome::Challenged(content, components) => (content, components),
+                    outcome => (outcome.message().to_string(), CreateComponents::default())
+                }
+            }
+            "submit" => {
+                let failed_attempts = challenge.failed_attempts + 1;
+                let mut updated: captcha_challenge::ActiveModel = challenge.into();
+
+                if failed_attempts >= server_model.captcha_attempts {
+                    let locked_until = now + Duration::minutes(server_model.captcha_lockout_minutes);
+                    updated.failed_attempts = Set(0);
+                    updated.locked_until = Set(Some(locked_until));
+                    updated.update(db).await
+                        .map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+                    (lockout_message(locked_until), CreateComponents::default())
+                } else {
+                    let answer = generate_answer();
+                    updated.puzzle = Set(render_puzzle(&answer));
+                    updated.answer = Set(answer);
+                    updated.input = Set(String::new());
+                    updated.failed_attempts = Set(failed_attempts);
+                    let updated = updated.update(db).await
+                        .map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+                    let remaining = server_model.captcha_attempts - failed_attempts;
+                    challenge_message(&updated, Some(&format!("That code was incorrect. You have {} attempt(s) remaining.", remaining)))
+                }
+            }
+            "new" => {
+                let answer = generate_answer();
+                let mut updated: captcha_challenge::ActiveModel = challenge.into();
+                updated.puzzle = Set(render_puzzle(&answer));
+                updated.answer = Set(answer);
+                updated.input = Set(String::new());
+                let updated = updated.update(db).await
+                    .map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+                challenge_message(&updated, None)
+            }
+            "back" | "0" | "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9" => {
+                let input = enter_key(&challenge.input, key);
+                if input == challenge.input {
+                    challenge_message(&challenge, None)
+                } else {
+                    let mut updated: captcha_challenge::ActiveModel = challenge.into();
+                    updated.input = Set(input);
+                    let updated = updated.update(db).await
+                        .map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+                    challenge_message(&updated, None)
+                }
+            }
+            unknown => return Err(RaincoatError { cause: format!("Unknown captcha key: {}", unknown) })
+        }
+    };
+
+    component.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::UpdateMessage)
+            .interaction_response_data(|message| {
+                message.content(content)
+                    .set_components(components)
+            })
+    }).await.map_err(|err| RaincoatError { cause: format!("Failed to respond to component: {}", err) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{enter_key, generate_answer, render_puzzle, CANVAS_HEIGHT, CODE_LENGTH, FILL_CHARS, GLYPHS};
+
+    #[test]
+    fn answers_are_digit_codes() {
+        let answer = generate_answer();
+        assert_eq!(answer.len(), CODE_LENGTH);
+        assert!(answer.chars().all(|c| c.is_ascii_digit()));
+    }
+
+    #[test]
+    fn puzzles_draw_every_digit() {
+        let answer = "01289";
+        let puzzle = render_puzzle(answer);
+        assert_eq!(puzzle.lines().count(), CANVAS_HEIGHT);
+
+        let expected_fill: usize = answer.chars()
+            .map(|digit| GLYPHS[digit.to_digit(10).unwrap() as usize].iter().map(|row| row.matches('#').count()).sum::<usize>())
+            .sum();
+        assert_eq!(puzzle.chars().filter(|c| FILL_CHARS.contains(c)).count(), expected_fill);
+        assert!(!puzzle.contains(answer));
+    }
+
+    #[test]
+    fn keys_build_up_the_answer() {
+        let input = "1234".chars().fold(String::new(), |input, key| enter_key(&input, &key.to_string()));
+        assert_eq!(input, "1234");
+        assert_eq!(enter_key(&input, "back"), "123");
+        assert_eq!(enter_key("", "back"), "");
+    }
+
+    #[test]
+    fn input_stops_at_the_code_length() {
+        let full = "12345";
+        assert_eq!(enter_key(full, "6"), full);
+        assert_eq!(enter_key(full, "back"), "1234");
+    }
+}
@@ -0,0 +1,199 @@
+use sea_orm::{DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, ColumnTrait, PaginatorTrait};
+use serenity::builder::{CreateApplicationCommandPermissions, CreateApplicationCommands, CreateComponents, CreateEmbed};
+use serenity::model::id::GuildId;
+use serenity::model::interactions::application_command::{ApplicationCommandInteraction, ApplicationCommandInteractionDataOptionValue, ApplicationCommandOptionType, ApplicationCommandPermissionType};
+use serenity::model::interactions::InteractionResponseType;
+use serenity::model::interactions::message_component::ButtonStyle;
+use serenity::model::prelude::message_component::MessageComponentInteraction;
+use serenity::model::prelude::InteractionApplicationCommandCallbackDataFlags;
+use serenity::prelude::Context;
+use crate::error::RaincoatError;
+use crate::model::punishment;
+use crate::model::punishment::PunishmentType;
+
+const CASES_PER_PAGE: usize = 5;
+
+pub fn create_command(commands: &mut CreateApplicationCommands) {
+    commands.create_application_command(|command| {
+        command.name("cases")
+            .description("List the moderation history of a user")
+            .default_permission(false)
+            .create_option(|option| {
+                option.name("user")
+                    .kind(ApplicationCommandOptionType::User)
+                    .description("The user to list cases for")
+                    .required(true)
+            })
+    });
+}
+
+pub fn create_permissions(mod_role: u64, updater: &mut CreateApplicationCommandPermissions) -> &mut CreateApplicationCommandPermissions {
+    updater.create_permissions(|permissions| {
+        permissions.kind(ApplicationCommandPermissionType::Role)
+            .id(mod_role)
+            .permission(true)
+    })
+}
+
+fn punishment_type_name(punishment_type: &PunishmentType) -> &'static str {
+    match punishment_type {
+        PunishmentType::Dunce => "Dunce",
+        PunishmentType::Ban => "Ban",
+        PunishmentType::Kick => "Kick",
+        PunishmentType::Timeout => "Timeout",
+        PunishmentType::Softban => "Softban"
+    }
+}
+
+fn case_description(case: &punishment::Model) -> String {
+    let mut lines = Vec::with_capacity(4);
+
+    match case.moderator_id {
+        Some(moderator_id) => lines.push(format!("Issued <t:{}> by <@{}>", case.created_at.timestamp(), moderator_id)),
+        None => lines.push(format!("Issued <t:{}>", case.created_at.timestamp()))
+    }
+    lines.push(format!("Reason: {}", case.reason.as_deref().unwrap_or("No reason given")));
+
+    match case.resolved_at {
+        Some(resolved_at) => {
+            if case.punishment_type != PunishmentType::Kick && case.punishment_type != PunishmentType::Softban {
+                let resolved_by = match case.resolved_by {
+                    Some(resolved_by) => format!(" by <@{}>", resolved_by),
+                    None => String::new()
+                };
+                let resolved_reason = match &case.resolved_reason {
+                    Some(resolved_reason) => format!(": {}", resolved_reason),
+                    None => String::new()
+                };
+                lines.push(format!("Resolved <t:{}>{}{}", resolved_at.timestamp(), resolved_by, resolved_reason));
+            }
+        }
+        None => {
+            match case.expires {
+                Some(expires) => lines.push(format!("Active until <t:{}>", expires.timestamp())),
+                None => lines.push("Active indefinitely".to_string())
+            }
+        }
+    }
+
+    lines.join("\n")
+}
+
+/// Builds one page of a user's case history, along with the buttons used to move between pages.
+async fn cases_page(db: &DatabaseConnection, server_id: GuildId, user_id: u64, page: usize) -> Result<(CreateEmbed, CreateComponents), RaincoatError> {
+    let paginator = punishment::Entity::find()
+        .filter(punishment::Column::ServerId.eq(server_id.0 as i64))
+        .filter(punishment::Column::UserId.eq(user_id as i64))
+        .order_by_desc(punishment::Column::CaseNumber)
+        .paginate(db, CASES_PER_PAGE);
+
+    let page_count = paginator.num_pages().await
+        .map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+    let page = page.min(page_count.saturating_sub(1));
+    let cases = paginator.fetch_page(page).await
+        .map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+    let mut embed = CreateEmbed::default();
+    embed.title("Case history");
+    if cases.is_empty() {
+        embed.description(format!("<@{}> has no cases on this server.", user_id));
+    } else {
+        embed.description(format!("Cases for <@{}>", user_id));
+        for case in &cases {
+            embed.field(format!("Case #{} \u{2014} {}", case.case_number, punishment_type_name(&case.punishment_type)),
+                        case_description(case), false);
+        }
+        embed.footer(|f| f.text(format!("Page {} of {}", page + 1, page_count)));
+    }
+
+    let mut components = CreateComponents::default();
+    if page_count > 1 {
+        components.create_action_row(|row| {
+            row.create_button(|button| {
+                button.custom_id(format!("cases:{}:{}", user_id, page.saturating_sub(1)))
+                    .label("Previous")
+                    .style(ButtonStyle::Secondary)
+                    .disabled(page == 0)
+            })
+                .create_button(|button| {
+                    button.custom_id(format!("cases:{}:{}", user_id, page + 1))
+                        .label("Next")
+                        .style(ButtonStyle::Secondary)
+                        .disabled(page + 1 >= page_count)
+                })
+        });
+    }
+
+    Ok((embed, components))
+}
+
+pub async fn create_response(db: &DatabaseConnection, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<(), RaincoatError> {
+    let server_id = command.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
+
+    let mut user_id_opt: Option<u64> = None;
+
+    for option in &command.data.options {
+        match option.name.as_str() {
+            "user" => {
+                if let ApplicationCommandInteractionDataOptionValue::User(user, _member) = &option.resolved.as_ref()
+                    .ok_or(RaincoatError { cause: "Couldn't resolve 'user' param".to_string() })? {
+                    user_id_opt = Some(user.id.0);
+                } else {
+                    return Err(RaincoatError { cause: "Unexpected type for 'user' param".to_string() })
+                }
+            }
+            unknown => {
+                return Err(RaincoatError { cause: format!("Unknown param: {}", unknown)})
+            }
+        }
+    }
+
+    let user_id = user_id_opt.ok_or(RaincoatError { cause: "Requires 'user' param".to_string() })?;
+
+    let (embed, components) = cases_page(db, server_id, user_id, 0).await?;
+
+    command.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                message.add_embed(embed)
+                    .set_components(components)
+                    .allowed_mentions(|f| f.empty_parse())
+            })
+    }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
+}
+
+pub async fn create_component_response(db: &DatabaseConnection, ctx: &Context, component: &MessageComponentInteraction) -> Result<(), RaincoatError> {
+    let server_id = component.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
+
+    // Only the moderator who ran /cases may page through the results
+    if let Some(interaction) = &component.message.interaction {
+        if interaction.user.id != component.user.id {
+            return component.create_interaction_response(&ctx.http, |response| {
+                response.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message.content("Only the user who ran this command can change pages.")
+                            .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                    })
+            }).await.map_err(|err| RaincoatError { cause: format!("Failed to respond to component: {}", err) });
+        }
+    }
+
+    let mut parts = component.data.custom_id.split(':').skip(1);
+    let user_id: u64 = parts.next()
+        .and_then(|user_id| user_id.parse().ok())
+        .ok_or(RaincoatError { cause: "Malformed case history button".to_string() })?;
+    let page: usize = parts.next()
+        .and_then(|page| page.parse().ok())
+        .ok_or(RaincoatError { cause: "Malformed case history button".to_string() })?;
+
+    let (embed, components) = cases_page(db, server_id, user_id, page).await?;
+
+    component.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::UpdateMessage)
+            .interaction_response_data(|message| {
+                message.embeds(vec![embed])
+                    .set_components(components)
+                    .allowed_mentions(|f| f.empty_parse())
+            })
+    }).await.map_err(|err| RaincoatError { cause: format!("Failed to respond to component: {}", err) })
+}
@@ -1,13 +1,23 @@
+use chrono::Duration;
 use sea_orm::ActiveValue::Set;
-use sea_orm::{DatabaseConnection, ActiveModelTrait, EntityTrait};
+use sea_orm::{DatabaseConnection, ActiveModelTrait, EntityTrait, QueryFilter, QueryOrder, ColumnTrait, ModelTrait};
 use serenity::builder::{CreateApplicationCommandPermissions, CreateApplicationCommands};
+use serenity::model::channel::ChannelType;
+use serenity::model::id::{ChannelId, GuildId, RoleId};
 use serenity::model::interactions::application_command::{ApplicationCommandOptionType, ApplicationCommandPermissionType};
-use serenity::model::interactions::application_command::ApplicationCommandInteractionDataOptionValue;
+use serenity::model::interactions::application_command::{ApplicationCommandInteractionDataOption, ApplicationCommandInteractionDataOptionValue};
 use serenity::model::prelude::application_command::{ApplicationCommandInteraction};
 use serenity::model::prelude::InteractionResponseType;
 use serenity::prelude::Context;
 use crate::error::RaincoatError;
+use crate::mod_log;
 use crate::model::optional_role;
+use crate::model::punishment_removed_role;
+use crate::model::reaction_role;
+use crate::model::role_group;
+use crate::model::server;
+use crate::model::temporary_role;
+use super::{punishments, role};
 
 pub fn create_command(commands: &mut CreateApplicationCommands) {
     commands.create_application_command(|command| {
@@ -32,6 +42,26 @@ pub fn create_command(commands: &mut CreateApplicationCommands) {
                     .description("A description for this role.")
                     .required(false)
             })
+            .create_option(|option| {
+                option.name("group")
+                    .kind(ApplicationCommandOptionType::String)
+                    .description("The name of the role group to put this role in.")
+                    .required(false)
+            })
+            .create_option(|option| {
+                option.name("requires")
+                    .kind(ApplicationCommandOptionType::String)
+                    .description("Roles, any of which a member must have to pick this role, e.g. @Verified @Level 5")
+                    .required(false)
+            })
+            .create_option(|option| {
+                option.name("excludes")
+                    .kind(ApplicationCommandOptionType::String)
+                    .description("Roles which stop a member from picking this role")
+                    .required(false)
+            });
+        // A duration makes the role temporary, removing it that long after it is picked
+        punishments::duration_add_options(command)
     });
 
     commands.create_application_command(|command| {
@@ -45,6 +75,73 @@ pub fn create_command(commands: &mut CreateApplicationCommands) {
                     .required(true)
             })
     });
+
+    commands.create_application_command(|command| {
+        command.name("rolegroup")
+            .description("Manage groups of optional roles, each with their own menu")
+            .default_permission(false)
+            .create_option(|option| {
+                option.name("create")
+                    .description("Create a role group, or change an existing one")
+                    .kind(ApplicationCommandOptionType::SubCommand)
+                    .create_sub_option(|suboption| {
+                        suboption.name("name")
+                            .description("The name of the group, e.g. Pronouns")
+                            .kind(ApplicationCommandOptionType::String)
+                            .required(true)
+                    })
+                    .create_sub_option(|suboption| {
+                        suboption.name("min")
+                            .description("The fewest roles a member can have from this group (default 0)")
+                            .kind(ApplicationCommandOptionType::Integer)
+                            .min_int_value(0)
+                            .max_int_value(25)
+                            .required(false)
+                    })
+                    .create_sub_option(|suboption| {
+                        suboption.name("max")
+                            .description("The most roles a member can have from this group (default 25)")
+                            .kind(ApplicationCommandOptionType::Integer)
+                            .min_int_value(1)
+                            .max_int_value(25)
+                            .required(false)
+                    })
+            })
+            .create_option(|option| {
+                option.name("delete")
+                    .description("Delete a role group, leaving its roles ungrouped")
+                    .kind(ApplicationCommandOptionType::SubCommand)
+                    .create_sub_option(|suboption| {
+                        suboption.name("name")
+                            .description("The name of the group to delete")
+                            .kind(ApplicationCommandOptionType::String)
+                            .required(true)
+                    })
+            })
+            .create_option(|option| {
+                option.name("list")
+                    .description("List the role groups on this server")
+                    .kind(ApplicationCommandOptionType::SubCommand)
+            })
+    });
+
+    commands.create_application_command(|command| {
+        command.name("rolemenu")
+            .description("Manage role menus that anyone can use")
+            .default_permission(false)
+            .create_option(|option| {
+                option.name("post")
+                    .description("Post a role menu in a channel, which is kept up to date as optional roles change")
+                    .kind(ApplicationCommandOptionType::SubCommand)
+                    .create_sub_option(|suboption| {
+                        suboption.name("channel")
+                            .description("The channel to post the role menu in")
+                            .kind(ApplicationCommandOptionType::Channel)
+                            .channel_types(&[ChannelType::Text])
+                            .required(true)
+                    })
+            })
+    });
 }
 
 pub fn create_permissions(mod_role: u64, updater: &mut CreateApplicationCommandPermissions) -> &mut CreateApplicationCommandPermissions {
@@ -62,6 +159,10 @@ pub async fn create_add_response(db: &DatabaseConnection, ctx: &Context, command
     let mut role_name_opt: Option<String> = None;
     let mut emoji_opt: Option<String> = None;
     let mut description_opt: Option<String> = None;
+    let mut group_opt: Option<String> = None;
+    let mut required_role_ids_opt: Option<String> = None;
+    let mut excluded_role_ids_opt: Option<String> = None;
+    let mut time_accumulator = Duration::zero();
 
     for option in &command.data.options {
         match option.name.as_str() {
@@ -90,20 +191,39 @@ pub async fn create_add_response(db: &DatabaseConnection, ctx: &Context, command
                     return Err(RaincoatError { cause: "Unexpected type for 'description' param".to_string() })
                 }
             }
-            unknown => {
-                return Err(RaincoatError { cause: format!("Unknown param: {}", unknown) });
-            }
+            "group" => group_opt = Some(punishments::parse_string_option("group", option)?),
+            "requires" => required_role_ids_opt = Some(parse_role_list_option("requires", option)?),
+            "excludes" => excluded_role_ids_opt = Some(parse_role_list_option("excludes", option)?),
+            other => punishments::duration_parse(&mut time_accumulator, other, option)?
         }
     }
 
     let role_id = role_id_opt.ok_or(RaincoatError { cause: "Requires 'role' param".to_string() })?;
     let role_name = role_name_opt.ok_or(RaincoatError { cause: "Requires 'role' param".to_string() })?;
 
+    if time_accumulator < Duration::zero() {
+        return Err(RaincoatError { cause: "The duration can't be negative".to_string() });
+    }
+    let duration_minutes = if time_accumulator == Duration::zero() {
+        None
+    } else {
+        Some(time_accumulator.num_minutes().max(1))
+    };
+
+    let group_id = match group_opt {
+        Some(group_name) => Some(find_group(db, server_id, &group_name).await?.id),
+        None => None
+    };
+
     let new_role = optional_role::ActiveModel {
         role_id: Set(role_id as i64),
         server_id: Set(server_id.0 as i64),
         emoji: Set(emoji_opt),
-        description: Set(description_opt)
+        description: Set(description_opt),
+        group_id: Set(group_id),
+        required_role_ids: Set(required_role_ids_opt),
+        excluded_role_ids: Set(excluded_role_ids_opt),
+        duration_minutes: Set(duration_minutes)
     };
     if optional_role::Entity::find_by_id(role_id as i64).one(db).await
         .map_err(|err| RaincoatError { cause: format!("{}", err)})?.is_some() {
@@ -114,16 +234,24 @@ pub async fn create_add_response(db: &DatabaseConnection, ctx: &Context, command
         new_role.insert(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
     }
 
+    let refresh_note = refresh_note(db, ctx, server_id).await;
+    let duration_note = match duration_minutes {
+        Some(duration_minutes) => format!(" It will be removed {} after being picked.", mod_log::format_duration(Duration::minutes(duration_minutes))),
+        None => String::new()
+    };
+
     command.create_interaction_response(&ctx.http, |response| {
         response.kind(InteractionResponseType::ChannelMessageWithSource)
             .interaction_response_data(|message| {
-                message.content(format!("Successfully configured `{}` as an optional role.", role_name))
+                message.content(format!("Successfully configured `{}` as an optional role.{}{}", role_name, duration_note, refresh_note))
                     .allowed_mentions(|f| f.empty_parse())
             })
     }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
 }
 
 pub async fn create_remove_response(db: &DatabaseConnection, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<(), RaincoatError> {
+    let server_id = command.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
+
     let mut role_id_opt: Option<u64> = None;
     let mut role_name_opt: Option<String> = None;
 
@@ -153,11 +281,321 @@ pub async fn create_remove_response(db: &DatabaseConnection, ctx: &Context, comm
     };
     optional_role::Entity::delete(old_role).exec(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
 
+    let refresh_note = refresh_note(db, ctx, server_id).await;
+
+    command.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                message.content(format!("Successfully removed `{}` as an optional role.{}", role_name, refresh_note))
+                    .allowed_mentions(|f| f.empty_parse())
+            })
+    }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
+}
+
+pub async fn create_role_menu_response(db: &DatabaseConnection, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<(), RaincoatError> {
+    let server_id = command.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
+
+    let subcommand = command.data.options.first().ok_or(RaincoatError { cause: "Command target is required.".to_string() })?;
+
+    let content = match subcommand.name.as_str() {
+        "post" => {
+            let mut channel_id_opt: Option<u64> = None;
+
+            for option in &subcommand.options {
+                match option.name.as_str() {
+                    "channel" => {
+                        if let ApplicationCommandInteractionDataOptionValue::Channel(channel) = &option.resolved.as_ref()
+                            .ok_or(RaincoatError { cause: "Couldn't resolve 'channel' param".to_string() })? {
+                            channel_id_opt = Some(channel.id.0);
+                        } else {
+                            return Err(RaincoatError { cause: "Unexpected type for 'channel' param".to_string() });
+                        }
+                    }
+                    unknown => return Err(RaincoatError { cause: format!("Unknown parameter: {}", unknown) })
+                }
+            }
+
+            let channel_id = channel_id_opt.ok_or(RaincoatError { cause: "Requires 'channel' param".to_string() })?;
+
+            role::post_role_menu(db, ctx, server_id, ChannelId(channel_id)).await?;
+
+            format!("Posted a role menu in <#{}>.", channel_id)
+        }
+        unknown => return Err(RaincoatError { cause: format!("Unknown subcommand: {}", unknown) })
+    };
+
+    command.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                message.content(content)
+            })
+    }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
+}
+
+pub async fn create_role_group_response(db: &DatabaseConnection, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<(), RaincoatError> {
+    let server_id = command.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
+
+    let subcommand = command.data.options.first().ok_or(RaincoatError { cause: "Command target is required.".to_string() })?;
+
+    let content = match subcommand.name.as_str() {
+        "create" => {
+            let mut name_opt: Option<String> = None;
+            let mut min_values: i64 = 0;
+            let mut max_values: i64 = 25;
+
+            for option in &subcommand.options {
+                match option.name.as_str() {
+                    "name" => name_opt = Some(punishments::parse_string_option("name", option)?),
+                    "min" => min_values = punishments::parse_integer_option("min", option)?,
+                    "max" => max_values = punishments::parse_integer_option("max", option)?,
+                    unknown => return Err(RaincoatError { cause: format!("Unknown parameter: {}", unknown) })
+                }
+            }
+
+            let name = name_opt.ok_or(RaincoatError { cause: "Requires 'name' param".to_string() })?;
+            if min_values > max_values {
+                return Err(RaincoatError { cause: "'min' can't be more than 'max'".to_string() });
+            }
+
+            let existing = role_group::Entity::find()
+                .filter(role_group::Column::ServerId.eq(server_id.0 as i64))
+                .filter(role_group::Column::Name.eq(name.clone()))
+                .one(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+            match existing {
+                Some(existing) => {
+                    let mut updated: role_group::ActiveModel = existing.into();
+                    updated.min_values = Set(min_values);
+                    updated.max_values = Set(max_values);
+                    updated.update(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+                }
+                None => {
+                    role_group::ActiveModel {
+                        server_id: Set(server_id.0 as i64),
+                        name: Set(name.clone()),
+                        min_values: Set(min_values),
+                        max_values: Set(max_values),
+                        ..Default::default()
+                    }.insert(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+                }
+            }
+
+            format!("Configured role group `{}`, allowing {} to {} role(s).{}", name, min_values, max_values, refresh_note(db, ctx, server_id).await)
+        }
+        "delete" => {
+            let mut name_opt: Option<String> = None;
+
+            for option in &subcommand.options {
+                match option.name.as_str() {
+                    "name" => name_opt = Some(punishments::parse_string_option("name", option)?),
+                    unknown => return Err(RaincoatError { cause: format!("Unknown parameter: {}", unknown) })
+                }
+            }
+
+            let name = name_opt.ok_or(RaincoatError { cause: "Requires 'name' param".to_string() })?;
+
+            find_group(db, server_id, &name).await?
+                .delete(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+            format!("Deleted role group `{}`.{}", name, refresh_note(db, ctx, server_id).await)
+        }
+        "list" => {
+            let groups: Vec<role_group::Model> = role_group::Entity::find()
+                .filter(role_group::Column::ServerId.eq(server_id.0 as i64))
+                .order_by_asc(role_group::Column::Id)
+                .all(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+            if groups.is_empty() {
+                "There are no role groups on this server.".to_string()
+            } else {
+                groups.iter()
+                    .map(|group| format!("`{}`: {} to {} role(s)", group.name, group.min_values, group.max_values))
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            }
+        }
+        unknown => return Err(RaincoatError { cause: format!("Unknown subcommand: {}", unknown) })
+    };
+
     command.create_interaction_response(&ctx.http, |response| {
         response.kind(InteractionResponseType::ChannelMessageWithSource)
             .interaction_response_data(|message| {
-                message.content(format!("Successfully removed `{}` as an optional role.", role_name))
+                message.content(content)
                     .allowed_mentions(|f| f.empty_parse())
             })
     }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
 }
+
+/// Parses a list of roles from an option, storing them as comma separated IDs.
+fn parse_role_list_option(name: &str, option: &ApplicationCommandInteractionDataOption) -> Result<String, RaincoatError> {
+    let value = punishments::parse_string_option(name, option)?;
+    let role_ids = role::parse_role_list(&value);
+    if role_ids.is_empty() {
+        return Err(RaincoatError { cause: format!("Couldn't find any roles in '{}' param", name) });
+    }
+
+    Ok(role_ids.iter()
+        .map(|role_id| role_id.0.to_string())
+        .collect::<Vec<String>>()
+        .join(","))
+}
+
+async fn find_group(db: &DatabaseConnection, server_id: GuildId, name: &str) -> Result<role_group::Model, RaincoatError> {
+    role_group::Entity::find()
+        .filter(role_group::Column::ServerId.eq(server_id.0 as i64))
+        .filter(role_group::Column::Name.eq(name))
+        .one(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?
+        .ok_or(RaincoatError { cause: format!("There is no role group called `{}`", name) })
+}
+
+/// Updates the server's posted role menus, returning a note for the moderator if that failed.
+async fn refresh_note(db: &DatabaseConnection, ctx: &Context, server_id: GuildId) -> String {
+    match role::refresh_role_menus(db, ctx, server_id).await {
+        Ok(()) => String::new(),
+        Err(err) => format!("\nCouldn't update the role menus: {}", err.cause)
+    }
+}
+
+/// Removes every reference to a role which has been deleted from Discord, and tells the moderators what changed.
+pub async fn forget_deleted_role(db: &DatabaseConnection, ctx: &Context, server_id: GuildId, role_id: RoleId) -> Result<(), RaincoatError> {
+    let mut changes = Vec::new();
+
+    let removed = optional_role::Entity::delete_many()
+        .filter(optional_role::Column::RoleId.eq(role_id.0 as i64))
+        .exec(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+    if removed.rows_affected > 0 {
+        changes.push("Removed it as an optional role.".to_string());
+    }
+
+    let optional_roles: Vec<optional_role::Model> = optional_role::Entity::find()
+        .filter(optional_role::Column::ServerId.eq(server_id.0 as i64))
+        .all(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+    let mut requirements_changed = false;
+    let mut ungated_roles = Vec::new();
+    for optional_role in optional_roles {
+        let required_role_ids = without_role(&optional_role.required_role_ids, role_id);
+        let excluded_role_ids = without_role(&optional_role.excluded_role_ids, role_id);
+        // A role gated only on the deleted role would otherwise be open to everyone, so it is removed instead
+        if optional_role.required_role_ids.is_some() && required_role_ids.is_none() {
+            ungated_roles.push(format!("<@&{}>", optional_role.role_id));
+            temporary_role::Entity::delete_many()
+                .filter(temporary_role::Column::ServerId.eq(optional_role.server_id))
+                .filter(temporary_role::Column::RoleId.eq(optional_role.role_id))
+                .exec(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+            optional_role.delete(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+            continue;
+        }
+        if required_role_ids != optional_role.required_role_ids || excluded_role_ids != optional_role.excluded_role_ids {
+            let mut updated: optional_role::ActiveModel = optional_role.into();
+            updated.required_role_ids = Set(required_role_ids);
+            updated.excluded_role_ids = Set(excluded_role_ids);
+            updated.update(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+            requirements_changed = true;
+        }
+    }
+    if requirements_changed {
+        changes.push("Removed it from the requirements and exclusions of other optional roles.".to_string());
+    }
+    if !ungated_roles.is_empty() {
+        changes.push(format!("Removed optional role(s) {} which required it and nothing else, so they aren't open to everyone. Add them again with new requirements to bring them back.", ungated_roles.join(", ")));
+    }
+
+    let removed = reaction_role::Entity::delete_many()
+        .filter(reaction_role::Column::RoleId.eq(role_id.0 as i64))
+        .exec(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+    if removed.rows_affected > 0 {
+        changes.push(format!("Removed {} reaction role(s) which gave it out.", removed.rows_affected));
+    }
+
+    temporary_role::Entity::delete_many()
+        .filter(temporary_role::Column::RoleId.eq(role_id.0 as i64))
+        .exec(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+    let removed = punishment_removed_role::Entity::delete_many()
+        .filter(punishment_removed_role::Column::RoleId.eq(role_id.0 as i64))
+        .exec(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+    if removed.rows_affected > 0 {
+        changes.push(format!("Stopped restoring it to {} punished user(s).", removed.rows_affected));
+    }
+
+    if let Some(server_model) = server::Entity::find_by_id(server_id.0 as i64).one(db).await
+        .map_err(|err| RaincoatError { cause: format!("{}", err) })? {
+        let role_id = role_id.0 as i64;
+        let mut updated = server::ActiveModel {
+            id: Set(server_model.id),
+            ..Default::default()
+        };
+        let mut server_changed = false;
+
+        if server_model.verified_role_id == Some(role_id) {
+            // Clear the rest of the setup like /verification disable does, so the old message stops verifying people
+            updated.verified_role_id = Set(None);
+            updated.verification_channel_id = Set(None);
+            updated.verification_message_id = Set(None);
+            updated.verification_emoji = Set(None);
+            updated.verification_timeout = Set(None);
+            server_changed = true;
+            changes.push("It was the verified role, so verification is disabled until it is set up again.".to_string());
+        }
+        if server_model.dunce_role_id == Some(role_id) {
+            updated.dunce_role_id = Set(None);
+            server_changed = true;
+            changes.push("It was the dunce role, so /dunce won't work until a new one is set.".to_string());
+        }
+        if server_model.mod_role_id == role_id {
+            changes.push("It was the moderator role, so moderator commands can't be used until a new one is set.".to_string());
+        }
+
+        if server_changed {
+            updated.update(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+        }
+    }
+
+    if changes.is_empty() {
+        return Ok(());
+    }
+
+    // The moderators should hear what changed even if the role menus couldn't be updated
+    let refreshed = role::refresh_role_menus(db, ctx, server_id).await;
+    if let Err(err) = &refreshed {
+        changes.push(format!("Couldn't update the role menus: {}", err.cause));
+    }
+
+    mod_log::post_notice(db, &ctx.http, server_id, "Role deleted",
+                         format!("Role {} was deleted.\n- {}", role_id.0, changes.join("\n- "))).await;
+
+    refreshed
+}
+
+/// Takes a role out of a comma separated list of roles, clearing the list if it ends up empty.
+fn without_role(role_ids: &Option<String>, role_id: RoleId) -> Option<String> {
+    let remaining: Vec<String> = role::parse_role_list(role_ids.as_deref()?)
+        .into_iter()
+        .filter(|id| *id != role_id)
+        .map(|id| id.0.to_string())
+        .collect();
+
+    if remaining.is_empty() {
+        None
+    } else {
+        Some(remaining.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serenity::model::id::RoleId;
+    use super::without_role;
+
+    #[test]
+    fn removes_the_role_from_the_list() {
+        assert_eq!(without_role(&Some("1,2,3".to_string()), RoleId(2)), Some("1,3".to_string()));
+        assert_eq!(without_role(&Some("1,2".to_string()), RoleId(4)), Some("1,2".to_string()));
+    }
+
+    #[test]
+    fn clears_lists_that_end_up_empty() {
+        assert_eq!(without_role(&Some("1".to_string()), RoleId(1)), None);
+        assert_eq!(without_role(&None, RoleId(1)), None);
+    }
+}
@@ -1,7 +1,19 @@
 mod role;
 mod manage_roles;
 mod verification;
+mod captcha;
+mod approval;
+mod rules;
 mod punishments;
+mod cases;
+mod modlog_config;
+mod warnings;
+mod reaction_roles;
+
+pub use verification::{begin_verification, parse_reminder_percents, VerificationOutcome};
+pub use reaction_roles::{handle_reaction_add, handle_reaction_remove};
+pub use manage_roles::forget_deleted_role;
+pub use approval::withdraw_request;
 
 use sea_orm::DatabaseConnection;
 use serenity::builder::{CreateApplicationCommands, CreateApplicationCommandsPermissions};
@@ -15,8 +27,13 @@ use crate::error::RaincoatError;
 pub fn create_commands(commands: &mut CreateApplicationCommands) -> &mut CreateApplicationCommands {
     role::create_command(commands);
     manage_roles::create_command(commands);
+    reaction_roles::create_command(commands);
     verification::create_command(commands);
+    rules::create_command(commands);
     punishments::create_command(commands);
+    cases::create_command(commands);
+    modlog_config::create_command(commands);
+    warnings::create_command(commands);
 
     commands
 }
@@ -36,12 +53,36 @@ pub fn set_command_permissions<'a>(mod_role: u64, updater: &'a mut CreateApplica
                     manage_roles::create_permissions(mod_role, c)
                 });
             }
+            "reactionrole" => {
+                updater.create_application_command(|c| {
+                    c.id(command.id.0);
+                    reaction_roles::create_permissions(mod_role, c)
+                });
+            }
+            "rolegroup" => {
+                updater.create_application_command(|c| {
+                    c.id(command.id.0);
+                    manage_roles::create_permissions(mod_role, c)
+                });
+            }
+            "rolemenu" => {
+                updater.create_application_command(|c| {
+                    c.id(command.id.0);
+                    manage_roles::create_permissions(mod_role, c)
+                });
+            }
             "verification" => {
                 updater.create_application_command(|c| {
                     c.id(command.id.0);
                     verification::create_permissions(mod_role, c)
                 });
             }
+            "rules" => {
+                updater.create_application_command(|c| {
+                    c.id(command.id.0);
+                    rules::create_permissions(mod_role, c)
+                });
+            }
             "dunce" => {
                 updater.create_application_command(|c| {
                     c.id(command.id.0);
@@ -66,6 +107,84 @@ pub fn set_command_permissions<'a>(mod_role: u64, updater: &'a mut CreateApplica
                     punishments::create_permissions(mod_role, c)
                 });
             }
+            "kick" => {
+                updater.create_application_command(|c| {
+                    c.id(command.id.0);
+                    punishments::create_permissions(mod_role, c)
+                });
+            }
+            "softban" => {
+                updater.create_application_command(|c| {
+                    c.id(command.id.0);
+                    punishments::create_permissions(mod_role, c)
+                });
+            }
+            "rolerestore" => {
+                updater.create_application_command(|c| {
+                    c.id(command.id.0);
+                    punishments::create_permissions(mod_role, c)
+                });
+            }
+            "timeout" => {
+                updater.create_application_command(|c| {
+                    c.id(command.id.0);
+                    punishments::create_permissions(mod_role, c)
+                });
+            }
+            "untimeout" => {
+                updater.create_application_command(|c| {
+                    c.id(command.id.0);
+                    punishments::create_permissions(mod_role, c)
+                });
+            }
+            "cases" => {
+                updater.create_application_command(|c| {
+                    c.id(command.id.0);
+                    cases::create_permissions(mod_role, c)
+                });
+            }
+            "modlog" => {
+                updater.create_application_command(|c| {
+                    c.id(command.id.0);
+                    modlog_config::create_permissions(mod_role, c)
+                });
+            }
+            "warn" => {
+                updater.create_application_command(|c| {
+                    c.id(command.id.0);
+                    warnings::create_permissions(mod_role, c)
+                });
+            }
+            "warnings" => {
+                updater.create_application_command(|c| {
+                    c.id(command.id.0);
+                    warnings::create_permissions(mod_role, c)
+                });
+            }
+            "clearwarn" => {
+                updater.create_application_command(|c| {
+                    c.id(command.id.0);
+                    warnings::create_permissions(mod_role, c)
+                });
+            }
+            "addescalation" => {
+                updater.create_application_command(|c| {
+                    c.id(command.id.0);
+                    warnings::create_permissions(mod_role, c)
+                });
+            }
+            "removeescalation" => {
+                updater.create_application_command(|c| {
+                    c.id(command.id.0);
+                    warnings::create_permissions(mod_role, c)
+                });
+            }
+            "escalations" => {
+                updater.create_application_command(|c| {
+                    c.id(command.id.0);
+                    warnings::create_permissions(mod_role, c)
+                });
+            }
             _ => {}
         }
     }
@@ -83,9 +202,24 @@ pub async fn create_command_response(db: &DatabaseConnection, ctx: &Context, com
         "removerole" => {
             manage_roles::create_remove_response(db, ctx, command).await
         }
+        "reactionrole" => {
+            reaction_roles::create_response(db, ctx, command).await
+        }
+        "rolegroup" => {
+            manage_roles::create_role_group_response(db, ctx, command).await
+        }
+        "rolemenu" => {
+            manage_roles::create_role_menu_response(db, ctx, command).await
+        }
         "verification" => {
             verification::create_response(db, ctx, command).await
         }
+        "rules" => {
+            rules::create_response(db, ctx, command).await
+        }
+        "answer" => {
+            rules::create_answer_response(db, ctx, command).await
+        }
         "dunce" => {
             punishments::create_dunce_response(db, ctx, command).await
         }
@@ -98,6 +232,45 @@ pub async fn create_command_response(db: &DatabaseConnection, ctx: &Context, com
         "unban" => {
             punishments::create_unban_response(db, ctx, command).await
         }
+        "kick" => {
+            punishments::create_kick_response(db, ctx, command).await
+        }
+        "softban" => {
+            punishments::create_softban_response(db, ctx, command).await
+        }
+        "rolerestore" => {
+            punishments::create_role_restore_response(db, ctx, command).await
+        }
+        "timeout" => {
+            punishments::create_timeout_response(db, ctx, command).await
+        }
+        "untimeout" => {
+            punishments::create_untimeout_response(db, ctx, command).await
+        }
+        "cases" => {
+            cases::create_response(db, ctx, command).await
+        }
+        "modlog" => {
+            modlog_config::create_response(db, ctx, command).await
+        }
+        "warn" => {
+            warnings::create_warn_response(db, ctx, command).await
+        }
+        "warnings" => {
+            warnings::create_warnings_response(db, ctx, command).await
+        }
+        "clearwarn" => {
+            warnings::create_clearwarn_response(db, ctx, command).await
+        }
+        "addescalation" => {
+            warnings::create_add_escalation_response(db, ctx, command).await
+        }
+        "removeescalation" => {
+            warnings::create_remove_escalation_response(db, ctx, command).await
+        }
+        "escalations" => {
+            warnings::create_escalations_response(db, ctx, command).await
+        }
         _ => {
             command.create_interaction_response(&ctx.http, |response| {
                 response.kind(InteractionResponseType::ChannelMessageWithSource)
@@ -111,9 +284,27 @@ pub async fn create_command_response(db: &DatabaseConnection, ctx: &Context, com
 
 pub async fn create_component_response(db: &DatabaseConnection, ctx: &Context, component: &MessageComponentInteraction) -> Result<(), RaincoatError> {
     match component.data.custom_id.as_str() {
-        "role_select" => {
+        custom_id if custom_id.starts_with("role_select") || custom_id.starts_with("role_menu") => {
             role::create_component_response(db, ctx, component).await
         }
+        custom_id if custom_id.starts_with("role_page:") => {
+            role::create_page_response(db, ctx, component).await
+        }
+        "verify" => {
+            verification::create_component_response(db, ctx, component).await
+        }
+        custom_id if custom_id.starts_with("captcha:") => {
+            captcha::create_component_response(db, ctx, component).await
+        }
+        custom_id if custom_id.starts_with("rules:") => {
+            rules::create_component_response(db, ctx, component).await
+        }
+        custom_id if custom_id.starts_with("approval:") => {
+            approval::create_component_response(db, ctx, component).await
+        }
+        custom_id if custom_id.starts_with("cases:") => {
+            cases::create_component_response(db, ctx, component).await
+        }
         _ => {
             component.create_interaction_response(&ctx.http, |response| {
                 response.kind(InteractionResponseType::ChannelMessageWithSource)
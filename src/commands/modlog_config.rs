@@ -0,0 +1,101 @@
+use sea_orm::ActiveValue::Set;
+use sea_orm::{DatabaseConnection, ActiveModelTrait};
+use serenity::builder::{CreateApplicationCommandPermissions, CreateApplicationCommands};
+use serenity::model::channel::ChannelType;
+use serenity::model::interactions::application_command::{ApplicationCommandInteractionDataOptionValue, ApplicationCommandOptionType};
+use serenity::model::interactions::InteractionResponseType;
+use serenity::model::prelude::application_command::{ApplicationCommandInteraction, ApplicationCommandPermissionType};
+use serenity::prelude::Context;
+use crate::error::RaincoatError;
+use crate::model::server;
+
+pub fn create_command(commands: &mut CreateApplicationCommands) {
+    commands.create_application_command(|command| {
+        command.name("modlog")
+            .description("Configure the moderation log for this server.")
+            .default_permission(false)
+            .create_option(|option| {
+                option.name("set")
+                    .description("Set the channel moderation actions are logged to")
+                    .kind(ApplicationCommandOptionType::SubCommand)
+                    .create_sub_option(|suboption| {
+                        suboption.name("channel")
+                            .description("The channel to log moderation actions to")
+                            .kind(ApplicationCommandOptionType::Channel)
+                            .channel_types(&[ChannelType::Text])
+                            .required(true)
+                    })
+            })
+            .create_option(|option| {
+                option.name("disable")
+                    .description("Stop logging moderation actions")
+                    .kind(ApplicationCommandOptionType::SubCommand)
+            })
+    });
+}
+
+pub fn create_permissions(mod_role: u64, updater: &mut CreateApplicationCommandPermissions) -> &mut CreateApplicationCommandPermissions {
+    updater.create_permissions(|permissions| {
+        permissions.kind(ApplicationCommandPermissionType::Role)
+            .id(mod_role)
+            .permission(true)
+    })
+}
+
+pub async fn create_response(db: &DatabaseConnection, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<(), RaincoatError> {
+    let server_id = command.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
+
+    let subcommand = command.data.options.first().ok_or(RaincoatError { cause: "Command target is required.".to_string() })?;
+
+    match subcommand.name.as_str() {
+        "set" => {
+            let mut channel_id_opt: Option<u64> = None;
+
+            for option in &subcommand.options {
+                match option.name.as_str() {
+                    "channel" => {
+                        if let ApplicationCommandInteractionDataOptionValue::Channel(channel) = &option.resolved.as_ref()
+                            .ok_or(RaincoatError { cause: "Couldn't resolve 'channel' param".to_string() })? {
+                            channel_id_opt = Some(channel.id.0);
+                        } else {
+                            return Err(RaincoatError { cause: "Unexpected type for 'channel' param".to_string() });
+                        }
+                    }
+                    unknown => return Err(RaincoatError { cause: format!("Unknown parameter: {}", unknown) })
+                }
+            }
+
+            let channel_id = channel_id_opt.ok_or(RaincoatError { cause: "Requires 'channel' param".to_string() })?;
+
+            let new_server = server::ActiveModel {
+                id: Set(server_id.0 as i64),
+                mod_log_channel_id: Set(Some(channel_id as i64)),
+                ..Default::default()
+            };
+            new_server.update(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+            command.create_interaction_response(&ctx.http, |response| {
+                response.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message.content(format!("Moderation actions will now be logged to <#{}>.", channel_id))
+                    })
+            }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
+        }
+        "disable" => {
+            let new_server = server::ActiveModel {
+                id: Set(server_id.0 as i64),
+                mod_log_channel_id: Set(None),
+                ..Default::default()
+            };
+            new_server.update(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+            command.create_interaction_response(&ctx.http, |response| {
+                response.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message.content("Successfully disabled the moderation log.")
+                    })
+            }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
+        }
+        unknown => Err(RaincoatError { cause: format!("Unknown subcommand: {}", unknown) })
+    }
+}
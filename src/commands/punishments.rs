@@ -1,86 +1,184 @@
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
 use sea_orm::ActiveValue::Set;
-use sea_orm::{DatabaseConnection, EntityTrait, ActiveModelTrait, QueryFilter, ColumnTrait, ModelTrait};
+use sea_orm::{DatabaseConnection, DbBackend, EntityTrait, ActiveModelTrait, ConnectionTrait, QueryFilter, ColumnTrait, ModelTrait, Statement};
 use serenity::builder::{CreateApplicationCommand, CreateApplicationCommandPermissions, CreateApplicationCommands};
 use serenity::model::interactions::application_command::{ApplicationCommandInteraction, ApplicationCommandInteractionDataOption, ApplicationCommandInteractionDataOptionValue, ApplicationCommandOptionType, ApplicationCommandPermissionType};
+use serenity::model::id::{GuildId, UserId};
 use serenity::model::interactions::InteractionResponseType;
 use serenity::prelude::{Context, Mentionable};
 use crate::error::RaincoatError;
+use crate::mod_log;
+use crate::mod_log::ModLogEntry;
+use crate::scheduler;
 use crate::model::punishment;
 use crate::model::punishment::PunishmentType;
 use crate::model::punishment_removed_role;
 use crate::model::server;
 
-fn parse_integer_option(name: &str, option: &ApplicationCommandInteractionDataOption) -> Result<i64, RaincoatError> {
+pub fn parse_integer_option(name: &str, option: &ApplicationCommandInteractionDataOption) -> Result<i64, RaincoatError> {
     if let ApplicationCommandInteractionDataOptionValue::Integer(value) = &option.resolved.as_ref()
         .ok_or(RaincoatError { cause: format!("Couldn't resolve '{}' param", name) })? {
         Ok(*value)
     } else {
-        return Err(RaincoatError { cause: format!("Unexpected type for '{}' param", name) })
+        Err(RaincoatError { cause: format!("Unexpected type for '{}' param", name) })
     }
 }
 
-fn duration_add_options(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+pub fn parse_string_option(name: &str, option: &ApplicationCommandInteractionDataOption) -> Result<String, RaincoatError> {
+    if let ApplicationCommandInteractionDataOptionValue::String(value) = &option.resolved.as_ref()
+        .ok_or(RaincoatError { cause: format!("Couldn't resolve '{}' param", name) })? {
+        Ok(value.clone())
+    } else {
+        Err(RaincoatError { cause: format!("Unexpected type for '{}' param", name) })
+    }
+}
+
+/// Parses a user ID, also accepting it in mention form.
+fn parse_user_id(value: &str) -> Result<u64, RaincoatError> {
+    value.trim()
+        .trim_start_matches("<@")
+        .trim_start_matches('!')
+        .trim_end_matches('>')
+        .parse()
+        .map_err(|_err| RaincoatError { cause: format!("Couldn't parse {} as a user id", value) })
+}
+
+fn parse_delete_message_days(option: &ApplicationCommandInteractionDataOption) -> Result<u8, RaincoatError> {
+    let days = parse_integer_option("delete_messages", option)?;
+    if !(0..=7).contains(&days) {
+        return Err(RaincoatError { cause: "Messages can only be deleted from the last 0-7 days".to_string() })
+    }
+    Ok(days as u8)
+}
+
+/// Builds the reason shown in the Discord audit log, crediting the moderator who issued the punishment.
+async fn audit_log_reason(ctx: &Context, moderator_id: Option<u64>, reason: &Option<String>) -> String {
+    let moderator = match moderator_id {
+        Some(moderator_id) => match ctx.cache.user(moderator_id).await {
+            Some(moderator) => moderator.tag(),
+            None => moderator_id.to_string()
+        },
+        None => "automatic".to_string()
+    };
+    let reason = match reason {
+        Some(reason) => format!("{} (by {})", reason, moderator),
+        None => format!("No reason given (by {})", moderator)
+    };
+
+    // Discord rejects audit log reasons longer than 512 characters
+    reason.chars().take(512).collect()
+}
+
+/// Allocates the case number the next punishment issued in a server should be filed under.
+///
+/// The counter is bumped in a single statement so concurrent punishments never share a number.
+async fn next_case_number(db: &DatabaseConnection, server_id: GuildId) -> Result<i64, RaincoatError> {
+    let result = db.query_one(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        "UPDATE servers SET last_case_number = last_case_number + 1 WHERE id = $1 RETURNING last_case_number",
+        vec![(server_id.0 as i64).into()]
+    )).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?
+        .ok_or(RaincoatError { cause: "Server is not configured.".to_string() })?;
+
+    result.try_get("", "last_case_number").map_err(|err| RaincoatError { cause: format!("{}", err) })
+}
+
+/// Marks a punishment as over, keeping it around as part of the server's case history.
+pub async fn resolve_punishment(db: &DatabaseConnection, punishment: punishment::Model, resolved_by: Option<u64>, resolved_reason: Option<String>) -> Result<punishment::Model, RaincoatError> {
+    let mut resolved: punishment::ActiveModel = punishment.into();
+    resolved.resolved_at = Set(Some(Utc::now().naive_utc()));
+    resolved.resolved_by = Set(resolved_by.map(|id| id as i64));
+    resolved.resolved_reason = Set(resolved_reason);
+
+    resolved.update(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })
+}
+
+fn punishment_details(punishment: &punishment::Model) -> String {
+    let mut details = Vec::with_capacity(4);
+
+    details.push(format!("Case: #{}", punishment.case_number));
+    if let Some(moderator_id) = punishment.moderator_id {
+        details.push(format!("Moderator: <@{}>", moderator_id));
+    }
+    details.push(format!("Issued: <t:{}>", punishment.created_at.timestamp()));
+    details.push(format!("Reason: {}", punishment.reason.as_deref().unwrap_or("No reason given")));
+
+    details.join("\n")
+}
+
+pub fn duration_add_options(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
     command.create_option(|option| {
         option.name("years")
             .description("Years (cumulative)")
             .kind(ApplicationCommandOptionType::Integer)
+            .min_int_value(0)
     })
         .create_option(|option| {
             option.name("months")
                 .description("Months (cumulative)")
                 .kind(ApplicationCommandOptionType::Integer)
+                .min_int_value(0)
         })
         .create_option(|option| {
             option.name("weeks")
                 .description("Weeks (cumulative)")
                 .kind(ApplicationCommandOptionType::Integer)
+                .min_int_value(0)
         })
         .create_option(|option| {
             option.name("days")
                 .description("Days (cumulative)")
                 .kind(ApplicationCommandOptionType::Integer)
+                .min_int_value(0)
         })
         .create_option(|option| {
             option.name("hours")
                 .description("Hours (cumulative)")
                 .kind(ApplicationCommandOptionType::Integer)
+                .min_int_value(0)
         })
         .create_option(|option| {
             option.name("minutes")
                 .description("Minutes (cumulative)")
                 .kind(ApplicationCommandOptionType::Integer)
+                .min_int_value(0)
         })
 }
 
-fn duration_parse(duration_accumulator: &mut Duration, name: &str, option: &ApplicationCommandInteractionDataOption) -> Result<(), RaincoatError> {
-    match name {
-        "years" => {
-            *duration_accumulator = *duration_accumulator + Duration::weeks(4 * 12 * parse_integer_option(name, option)?);
-        }
-        "months" => {
-            *duration_accumulator = *duration_accumulator + Duration::weeks(4 * parse_integer_option(name, option)?);
-        }
-        "weeks" => {
-            *duration_accumulator = *duration_accumulator + Duration::weeks(parse_integer_option(name, option)?);
-        }
-        "days" => {
-            *duration_accumulator = *duration_accumulator + Duration::days(parse_integer_option(name, option)?);
-        }
-        "hours" => {
-            *duration_accumulator = *duration_accumulator + Duration::hours(parse_integer_option(name, option)?);
-        }
-        "minutes" => {
-            *duration_accumulator = *duration_accumulator + Duration::minutes(parse_integer_option(name, option)?);
-        }
+/// Builds a duration of `value` units of `unit_seconds` each, if it fits in a [`Duration`].
+fn checked_duration(value: i64, unit_seconds: i64) -> Option<Duration> {
+    value.checked_mul(unit_seconds)
+        .filter(|seconds| seconds.unsigned_abs() <= (i64::MAX / 1000) as u64)
+        .map(Duration::seconds)
+}
+
+pub fn duration_parse(duration_accumulator: &mut Duration, name: &str, option: &ApplicationCommandInteractionDataOption) -> Result<(), RaincoatError> {
+    let unit_seconds = match name {
+        "years" => 4 * 12 * 7 * 24 * 60 * 60,
+        "months" => 4 * 7 * 24 * 60 * 60,
+        "weeks" => 7 * 24 * 60 * 60,
+        "days" => 24 * 60 * 60,
+        "hours" => 60 * 60,
+        "minutes" => 60,
         unknown => {
             return Err(RaincoatError { cause: format!("Unknown param: {}", unknown) });
         }
     };
 
+    *duration_accumulator = checked_duration(parse_integer_option(name, option)?, unit_seconds)
+        .and_then(|duration| duration_accumulator.checked_add(&duration))
+        .ok_or(RaincoatError { cause: "That duration is too long".to_string() })?;
+
     Ok(())
 }
 
+/// Works out when something lasting `duration` from now will end.
+pub fn expires_after(duration: Duration) -> Result<NaiveDateTime, RaincoatError> {
+    Utc::now().checked_add_signed(duration)
+        .map(|expires| expires.naive_utc())
+        .ok_or(RaincoatError { cause: "That duration is too long".to_string() })
+}
+
 pub fn create_command(commands: &mut CreateApplicationCommands) {
     commands.create_application_command(|command| {
         command.name("dunce")
@@ -91,6 +189,12 @@ pub fn create_command(commands: &mut CreateApplicationCommands) {
                     .kind(ApplicationCommandOptionType::User)
                     .description("The user to dunce")
                     .required(true)
+            })
+            .create_option(|option| {
+                option.name("reason")
+                    .kind(ApplicationCommandOptionType::String)
+                    .description("The reason for the dunce")
+                    .required(false)
             });
         duration_add_options(command)
     });
@@ -105,6 +209,12 @@ pub fn create_command(commands: &mut CreateApplicationCommands) {
                     .description("The user to undunce")
                     .required(true)
             })
+            .create_option(|option| {
+                option.name("reason")
+                    .kind(ApplicationCommandOptionType::String)
+                    .description("The reason for lifting the punishment")
+                    .required(false)
+            })
     });
 
     commands.create_application_command(|command| {
@@ -115,7 +225,27 @@ pub fn create_command(commands: &mut CreateApplicationCommands) {
                 option.name("user")
                     .kind(ApplicationCommandOptionType::User)
                     .description("The user to ban")
-                    .required(true)
+                    .required(false)
+            })
+            .create_option(|option| {
+                option.name("user_id")
+                    .kind(ApplicationCommandOptionType::String)
+                    .description("The ID of the user to ban, for users who are not in the server")
+                    .required(false)
+            })
+            .create_option(|option| {
+                option.name("reason")
+                    .kind(ApplicationCommandOptionType::String)
+                    .description("The reason for the ban")
+                    .required(false)
+            })
+            .create_option(|option| {
+                option.name("delete_messages")
+                    .kind(ApplicationCommandOptionType::Integer)
+                    .description("Days of the user's recent messages to delete (0-7, defaults to 0)")
+                    .min_int_value(0)
+                    .max_int_value(7)
+                    .required(false)
             });
         duration_add_options(command)
     });
@@ -130,6 +260,112 @@ pub fn create_command(commands: &mut CreateApplicationCommands) {
                     .description("The user to unban")
                     .required(true)
             })
+            .create_option(|option| {
+                option.name("reason")
+                    .kind(ApplicationCommandOptionType::String)
+                    .description("The reason for lifting the punishment")
+                    .required(false)
+            })
+    });
+
+    commands.create_application_command(|command| {
+        command.name("kick")
+            .description("Kick a user from the server")
+            .default_permission(false)
+            .create_option(|option| {
+                option.name("user")
+                    .kind(ApplicationCommandOptionType::User)
+                    .description("The user to kick")
+                    .required(true)
+            })
+            .create_option(|option| {
+                option.name("reason")
+                    .kind(ApplicationCommandOptionType::String)
+                    .description("The reason for the kick")
+                    .required(true)
+            })
+            .create_option(|option| {
+                option.name("dm")
+                    .kind(ApplicationCommandOptionType::Boolean)
+                    .description("Whether to DM the user the reason before kicking them")
+                    .required(false)
+            })
+    });
+
+    commands.create_application_command(|command| {
+        command.name("softban")
+            .description("Ban and immediately unban a user, deleting their recent messages")
+            .default_permission(false)
+            .create_option(|option| {
+                option.name("user")
+                    .kind(ApplicationCommandOptionType::User)
+                    .description("The user to softban")
+                    .required(true)
+            })
+            .create_option(|option| {
+                option.name("reason")
+                    .kind(ApplicationCommandOptionType::String)
+                    .description("The reason for the softban")
+                    .required(false)
+            })
+            .create_option(|option| {
+                option.name("delete_messages")
+                    .kind(ApplicationCommandOptionType::Integer)
+                    .description("Days of the user's recent messages to delete (1-7, defaults to 1)")
+                    .min_int_value(1)
+                    .max_int_value(7)
+                    .required(false)
+            })
+    });
+
+    commands.create_application_command(|command| {
+        command.name("rolerestore")
+            .description("Set how long roles are kept for banned users, to be restored if they rejoin")
+            .default_permission(false)
+            .create_option(|option| {
+                option.name("hours")
+                    .kind(ApplicationCommandOptionType::Integer)
+                    .description("Hours after a ban ends that the user's roles are kept (0 to never restore roles)")
+                    .min_int_value(0)
+                    .required(true)
+            })
+    });
+
+    commands.create_application_command(|command| {
+        command.name("timeout")
+            .description("Time out a user for some amount of time (up to 28 days)")
+            .default_permission(false)
+            .create_option(|option| {
+                option.name("user")
+                    .kind(ApplicationCommandOptionType::User)
+                    .description("The user to time out")
+                    .required(true)
+            })
+            .create_option(|option| {
+                option.name("reason")
+                    .kind(ApplicationCommandOptionType::String)
+                    .description("The reason for the timeout")
+                    .required(false)
+            });
+        duration_add_options(command)
+    });
+
+    commands.create_application_command(|command| {
+        command.name("untimeout")
+            .description("Removes a user's timeout")
+            .default_permission(false)
+            .create_option(|option| {
+                option.name("user")
+                    .kind(ApplicationCommandOptionType::User)
+                    .description("The user to remove the timeout from")
+                    .required(true)
+            })
+            .create_option(|option| {
+                option.name("reason")
+                    .kind(ApplicationCommandOptionType::String)
+                    .description("The reason for lifting the punishment")
+                    .required(false)
+            })
     });
 }
 
@@ -141,52 +377,87 @@ pub fn create_permissions(mod_role: u64, updater: &mut CreateApplicationCommandP
     })
 }
 
-pub async fn create_dunce_response(db: &DatabaseConnection, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<(), RaincoatError> {
-    let server_id = command.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
+/// Dunces a member, saving the roles taken from them so they can be returned once the dunce is lifted.
+pub async fn dunce_user(db: &DatabaseConnection, ctx: &Context, server_id: GuildId, user_id: u64, moderator_id: Option<u64>, reason: Option<String>, expires: Option<NaiveDateTime>) -> Result<punishment::Model, RaincoatError> {
     let server_model: server::Model = server::Entity::find_by_id(server_id.0 as i64).one(db).await
         .map_err(|err| RaincoatError { cause: format!("{}", err) })?
         .ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
     let dunce_role_id = server_model.dunce_role_id
         .ok_or(RaincoatError { cause: "No dunce role has been configured for this server.".to_string() })? as u64;
 
-    let mut user_id_opt: Option<u64> = None;
-    let mut time_accumulator: Duration = Duration::zero();
+    let mut member = ctx.cache.member(server_id, user_id).await
+        .ok_or(RaincoatError { cause: "Unable to fetch information about user".to_string() })?;
+    let roles = member.roles.clone();
 
-    for option in &command.data.options {
-        match option.name.as_str() {
-            "user" => {
-                if let ApplicationCommandInteractionDataOptionValue::User(user, _member) = &option.resolved.as_ref()
-                    .ok_or(RaincoatError { cause: "Couldn't resolve 'user' param".to_string() })? {
-                    user_id_opt = Some(user.id.0);
-                } else {
-                    return Err(RaincoatError { cause: "Unexpected type for 'user' param".to_string() })
-                }
-            }
-            other => duration_parse(&mut time_accumulator, other, option)?
+    // Only record the dunce once it went through, so a failed dunce doesn't leave a case and saved roles behind
+    member.remove_roles(&ctx.http, roles.as_slice()).await
+        .map_err(|err| RaincoatError { cause: format!("Couldn't remove roles: {}", err) })?;
+    if let Err(err) = member.add_role(&ctx.http, dunce_role_id).await {
+        if let Err(restore_err) = member.add_roles(&ctx.http, roles.as_slice()).await {
+            eprintln!("Failed to give user {} back their roles after a failed dunce: {}", user_id, restore_err);
         }
+        return Err(RaincoatError { cause: format!("Couldn't add dunce role: {}", err) });
+    }
+
+    let new_punishment = punishment::ActiveModel {
+        user_id: Set(user_id as i64),
+        server_id: Set(server_id.0 as i64),
+        punishment_type: Set(PunishmentType::Dunce),
+        expires: Set(expires),
+        moderator_id: Set(moderator_id.map(|id| id as i64)),
+        reason: Set(reason),
+        created_at: Set(Utc::now().naive_utc()),
+        case_number: Set(next_case_number(db, server_id).await?),
+        ..Default::default()
     };
+    let punishment_model: punishment::Model = new_punishment.insert(db)
+        .await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
 
-    let user_id = user_id_opt.ok_or(RaincoatError { cause: "Requires 'user' param".to_string() })?;
-    let punishment_expires = if time_accumulator == Duration::zero() {
-        None
-    } else {
-        Some((Utc::now() + time_accumulator).naive_utc())
+    for role_id in &roles {
+        let new_punishment_removed_role = punishment_removed_role::ActiveModel {
+            punishment_id: Set(punishment_model.id),
+            role_id: Set(role_id.0 as i64),
+            ..Default::default()
+        };
+        new_punishment_removed_role.insert(db)
+            .await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+    }
+
+    mod_log::post(db, &ctx.http, server_id, ModLogEntry::for_punishment("Dunce", &punishment_model)).await;
+    scheduler::wake(ctx).await;
+
+    Ok(punishment_model)
+}
+
+/// Bans a user, saving the roles they held at the time of the ban if they are in the server.
+///
+/// The user does not need to be in the server, so known raiders can be banned ahead of time.
+#[allow(clippy::too_many_arguments)]
+pub async fn ban_user(db: &DatabaseConnection, ctx: &Context, server_id: GuildId, user_id: u64, moderator_id: Option<u64>, reason: Option<String>, expires: Option<NaiveDateTime>, delete_message_days: u8) -> Result<punishment::Model, RaincoatError> {
+    let roles = match ctx.cache.member(server_id, user_id).await {
+        Some(member) => member.roles,
+        None => Vec::new()
     };
+    let audit_reason = audit_log_reason(ctx, moderator_id, &reason).await;
+
+    // Only record the ban once it went through, so a failed ban doesn't leave a case and saved roles behind
+    server_id.ban_with_reason(&ctx.http, user_id, delete_message_days, audit_reason).await
+        .map_err(|err| RaincoatError { cause: format!("Unable to ban user: {}", err) })?;
 
     let new_punishment = punishment::ActiveModel {
         user_id: Set(user_id as i64),
         server_id: Set(server_id.0 as i64),
-        punishment_type: Set(PunishmentType::Dunce),
-        expires: Set(punishment_expires),
+        punishment_type: Set(PunishmentType::Ban),
+        expires: Set(expires),
+        moderator_id: Set(moderator_id.map(|id| id as i64)),
+        reason: Set(reason),
+        created_at: Set(Utc::now().naive_utc()),
+        case_number: Set(next_case_number(db, server_id).await?),
         ..Default::default()
     };
     let punishment_model: punishment::Model = new_punishment.insert(db)
         .await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
 
-    let mut member = ctx.cache.member(server_id, user_id).await
-        .ok_or(RaincoatError { cause: format!("Unable to fetch information about user") })?;
-    let roles = member.roles.clone();
-
     for role_id in &roles {
         let new_punishment_removed_role = punishment_removed_role::ActiveModel {
             punishment_id: Set(punishment_model.id),
@@ -197,21 +468,91 @@ pub async fn create_dunce_response(db: &DatabaseConnection, ctx: &Context, comma
             .await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
     }
 
-    member.remove_roles(&ctx.http, roles.as_slice()).await
-        .map_err(|err| RaincoatError { cause: format!("Couldn't remove roles: {}", err) })?;
-    member.add_role(&ctx.http, dunce_role_id).await
-        .map_err(|err| RaincoatError { cause: format!("Couldn't add dunce role: {}", err)})?;
+    mod_log::post(db, &ctx.http, server_id, ModLogEntry::for_punishment("Ban", &punishment_model)).await;
+    scheduler::wake(ctx).await;
+
+    Ok(punishment_model)
+}
+
+/// Checks that a timeout duration is one Discord will accept.
+pub fn check_timeout_duration(duration: Option<Duration>) -> Result<Duration, RaincoatError> {
+    match duration {
+        None => Err(RaincoatError { cause: "Timeouts require a duration.".to_string() }),
+        Some(duration) if duration <= Duration::zero() => Err(RaincoatError { cause: "Timeouts must last a positive amount of time.".to_string() }),
+        Some(duration) if duration > Duration::days(28) => Err(RaincoatError { cause: "Timeouts can last at most 28 days.".to_string() }),
+        Some(duration) => Ok(duration)
+    }
+}
+
+/// Times out a member using Discord's native communication timeout.
+pub async fn timeout_user(db: &DatabaseConnection, ctx: &Context, server_id: GuildId, user_id: u64, moderator_id: Option<u64>, reason: Option<String>, expires: NaiveDateTime) -> Result<punishment::Model, RaincoatError> {
+    let mut member = ctx.cache.member(server_id, user_id).await
+        .ok_or(RaincoatError { cause: "Unable to fetch information about user".to_string() })?;
+
+    member.disable_communication_until_datetime(&ctx.http, DateTime::from_utc(expires, Utc)).await
+        .map_err(|err| RaincoatError { cause: format!("Unable to time out user: {}", err) })?;
+
+    let new_punishment = punishment::ActiveModel {
+        user_id: Set(user_id as i64),
+        server_id: Set(server_id.0 as i64),
+        punishment_type: Set(PunishmentType::Timeout),
+        expires: Set(Some(expires)),
+        moderator_id: Set(moderator_id.map(|id| id as i64)),
+        reason: Set(reason),
+        created_at: Set(Utc::now().naive_utc()),
+        case_number: Set(next_case_number(db, server_id).await?),
+        ..Default::default()
+    };
+    let punishment_model: punishment::Model = new_punishment.insert(db)
+        .await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+    mod_log::post(db, &ctx.http, server_id, ModLogEntry::for_punishment("Timeout", &punishment_model)).await;
+    scheduler::wake(ctx).await;
+
+    Ok(punishment_model)
+}
+
+pub async fn create_dunce_response(db: &DatabaseConnection, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<(), RaincoatError> {
+    let server_id = command.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
+
+    let mut user_id_opt: Option<u64> = None;
+    let mut reason_opt: Option<String> = None;
+    let mut time_accumulator: Duration = Duration::zero();
+
+    for option in &command.data.options {
+        match option.name.as_str() {
+            "user" => {
+                if let ApplicationCommandInteractionDataOptionValue::User(user, _member) = &option.resolved.as_ref()
+                    .ok_or(RaincoatError { cause: "Couldn't resolve 'user' param".to_string() })? {
+                    user_id_opt = Some(user.id.0);
+                } else {
+                    return Err(RaincoatError { cause: "Unexpected type for 'user' param".to_string() })
+                }
+            }
+            "reason" => reason_opt = Some(parse_string_option("reason", option)?),
+            other => duration_parse(&mut time_accumulator, other, option)?
+        }
+    };
+
+    let user_id = user_id_opt.ok_or(RaincoatError { cause: "Requires 'user' param".to_string() })?;
+    let punishment_expires = if time_accumulator == Duration::zero() {
+        None
+    } else {
+        Some(expires_after(time_accumulator)?)
+    };
+
+    let punishment_model = dunce_user(db, ctx, server_id, user_id, Some(command.user.id.0), reason_opt, punishment_expires).await?;
 
     command.create_interaction_response(&ctx.http, |response| {
         response.kind(InteractionResponseType::ChannelMessageWithSource)
             .interaction_response_data(|message| {
                 match punishment_expires {
                     Some(expires) => {
-                        message.content(format!("Dunced {} until <t:{}>", member.mention().to_string(), expires.timestamp()))
+                        message.content(format!("Dunced {} until <t:{}>\n{}", UserId(user_id).mention(), expires.timestamp(), punishment_details(&punishment_model)))
                             .allowed_mentions(|f| f.empty_parse())
                     }
                     None => {
-                        message.content(format!("Dunced {} indefinitely", member.mention().to_string()))
+                        message.content(format!("Dunced {} indefinitely\n{}", UserId(user_id).mention(), punishment_details(&punishment_model)))
                             .allowed_mentions(|f| f.empty_parse())
                     }
                 }
@@ -228,6 +569,7 @@ pub async fn create_undunce_response(db: &DatabaseConnection, ctx: &Context, com
         .ok_or(RaincoatError { cause: "No dunce role has been configured for this server.".to_string() })? as u64;
 
     let mut user_id_opt: Option<u64> = None;
+    let mut reason_opt: Option<String> = None;
 
     for option in &command.data.options {
         match option.name.as_str() {
@@ -239,6 +581,7 @@ pub async fn create_undunce_response(db: &DatabaseConnection, ctx: &Context, com
                     return Err(RaincoatError { cause: "Unexpected type for 'user' param".to_string() })
                 }
             }
+            "reason" => reason_opt = Some(parse_string_option("reason", option)?),
             unknown => {
                 return Err(RaincoatError { cause: format!("Unknown param: {}", unknown)})
             }
@@ -251,29 +594,45 @@ pub async fn create_undunce_response(db: &DatabaseConnection, ctx: &Context, com
         .filter(punishment::Column::PunishmentType.eq(PunishmentType::Dunce))
         .filter(punishment::Column::UserId.eq(user_id as i64))
         .filter(punishment::Column::ServerId.eq(server_id.0 as i64))
+        .filter(punishment::Column::ResolvedAt.is_null())
         .find_with_related(punishment_removed_role::Entity)
         .all(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
 
-    if user_dunces.len() > 0 {
+    if !user_dunces.is_empty() {
+        let mut dunces = Vec::new();
         for (dunce, roles) in user_dunces {
-            dunce.delete(db).await
-                .map_err(|err| RaincoatError { cause: format!("{}", err) })?;
-
             for role in roles {
                 ctx.http.add_member_role(server_id.0, user_id, role.role_id as u64).await
                     .map_err(|err| RaincoatError { cause: format!("Unable to return user role: {}", err) })?;
                 role.delete(db).await
                     .map_err(|err| RaincoatError { cause: format!("{}", err) })?;
             }
+            dunces.push(dunce);
         };
 
         ctx.http.remove_member_role(server_id.0, user_id, dunce_role_id).await
             .map_err(|err| RaincoatError { cause: format!("Unable to remove dunce role: {}", err) })?;
 
+        for dunce in dunces {
+            let resolved = resolve_punishment(db, dunce, Some(command.user.id.0), reason_opt.clone()).await?;
+            mod_log::post(db, &ctx.http, server_id, ModLogEntry {
+                action: "Undunce".to_string(),
+                target_id: user_id,
+                moderator_id: Some(command.user.id.0),
+                case_number: Some(resolved.case_number),
+                duration: None,
+                reason: reason_opt.clone()
+            }).await;
+        }
+        scheduler::wake(ctx).await;
+
         command.create_interaction_response(&ctx.http, |response| {
             response.kind(InteractionResponseType::ChannelMessageWithSource)
                 .interaction_response_data(|message| {
-                    message.content(format!("Undunced <@{}>", user_id))
+                    match &reason_opt {
+                        Some(reason) => message.content(format!("Undunced <@{}>\nReason: {}", user_id, reason)),
+                        None => message.content(format!("Undunced <@{}>", user_id))
+                    }
                         .allowed_mentions(|f| f.empty_parse())
                 })
         }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
@@ -292,6 +651,8 @@ pub async fn create_ban_response(db: &DatabaseConnection, ctx: &Context, command
     let server_id = command.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
 
     let mut user_id_opt: Option<u64> = None;
+    let mut reason_opt: Option<String> = None;
+    let mut delete_message_days: u8 = 0;
     let mut time_accumulator: Duration = Duration::zero();
 
     for option in &command.data.options {
@@ -299,70 +660,335 @@ pub async fn create_ban_response(db: &DatabaseConnection, ctx: &Context, command
             "user" => {
                 if let ApplicationCommandInteractionDataOptionValue::User(user, _member) = &option.resolved.as_ref()
                     .ok_or(RaincoatError { cause: "Couldn't resolve 'user' param".to_string() })? {
+                    if user_id_opt.is_some() {
+                        return Err(RaincoatError { cause: "Only one of 'user' and 'user_id' may be given".to_string() })
+                    }
                     user_id_opt = Some(user.id.0);
                 } else {
                     return Err(RaincoatError { cause: "Unexpected type for 'user' param".to_string() })
                 }
             }
+            "user_id" => {
+                if user_id_opt.is_some() {
+                    return Err(RaincoatError { cause: "Only one of 'user' and 'user_id' may be given".to_string() })
+                }
+                user_id_opt = Some(parse_user_id(&parse_string_option("user_id", option)?)?);
+            }
+            "reason" => reason_opt = Some(parse_string_option("reason", option)?),
+            "delete_messages" => delete_message_days = parse_delete_message_days(option)?,
             other => duration_parse(&mut time_accumulator, other, option)?
         }
     };
 
-    let user_id = user_id_opt.ok_or(RaincoatError { cause: "Requires 'user' param".to_string() })?;
+    let user_id = user_id_opt.ok_or(RaincoatError { cause: "Requires 'user' or 'user_id' param".to_string() })?;
     let punishment_expires = if time_accumulator == Duration::zero() {
         None
     } else {
-        Some((Utc::now() + time_accumulator).naive_utc())
+        Some(expires_after(time_accumulator)?)
     };
 
+    let punishment_model = ban_user(db, ctx, server_id, user_id, Some(command.user.id.0), reason_opt, punishment_expires, delete_message_days).await?;
+
+    command.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                match punishment_expires {
+                    Some(expires) => {
+                        message.content(format!("Banned {} until <t:{}>\n{}", UserId(user_id).mention(), expires.timestamp(), punishment_details(&punishment_model)))
+                            .allowed_mentions(|f| f.empty_parse())
+                    }
+                    None => {
+                        message.content(format!("Banned {} indefinitely\n{}", UserId(user_id).mention(), punishment_details(&punishment_model)))
+                            .allowed_mentions(|f| f.empty_parse())
+                    }
+                }
+            })
+    }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
+}
+
+pub async fn create_unban_response(db: &DatabaseConnection, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<(), RaincoatError> {
+    let server_id = command.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
+
+    let mut user_id_opt: Option<u64> = None;
+    let mut reason_opt: Option<String> = None;
+
+    for option in &command.data.options {
+        match option.name.as_str() {
+            "user" => {
+                if let ApplicationCommandInteractionDataOptionValue::User(user, _member) = &option.resolved.as_ref()
+                    .ok_or(RaincoatError { cause: "Couldn't resolve 'user' param".to_string() })? {
+                    user_id_opt = Some(user.id.0);
+                } else {
+                    return Err(RaincoatError { cause: "Unexpected type for 'user' param".to_string() })
+                }
+            }
+            "reason" => reason_opt = Some(parse_string_option("reason", option)?),
+            unknown => {
+                return Err(RaincoatError { cause: format!("Unknown param: {}", unknown)})
+            }
+        }
+    }
+
+    let user_id = user_id_opt.ok_or(RaincoatError { cause: "Requires 'user' param".to_string() })?;
+
+    let user_bans: Vec<punishment::Model> = punishment::Entity::find()
+        .filter(punishment::Column::PunishmentType.eq(PunishmentType::Ban))
+        .filter(punishment::Column::UserId.eq(user_id as i64))
+        .filter(punishment::Column::ServerId.eq(server_id.0 as i64))
+        .filter(punishment::Column::ResolvedAt.is_null())
+        .all(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+    if !user_bans.is_empty() {
+        ctx.http.remove_ban(server_id.0, user_id).await
+            .map_err(|err| RaincoatError { cause: format!("Unable to unban user: {}", err) })?;
+
+        // The roles saved with each ban are kept, so they can be restored if the user rejoins
+        for ban in user_bans {
+            let resolved = resolve_punishment(db, ban, Some(command.user.id.0), reason_opt.clone()).await?;
+            mod_log::post(db, &ctx.http, server_id, ModLogEntry {
+                action: "Unban".to_string(),
+                target_id: user_id,
+                moderator_id: Some(command.user.id.0),
+                case_number: Some(resolved.case_number),
+                duration: None,
+                reason: reason_opt.clone()
+            }).await;
+        }
+        scheduler::wake(ctx).await;
+
+        command.create_interaction_response(&ctx.http, |response| {
+            response.kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    match &reason_opt {
+                        Some(reason) => message.content(format!("Unbanned <@{}>\nReason: {}", user_id, reason)),
+                        None => message.content(format!("Unbanned <@{}>", user_id))
+                    }
+                        .allowed_mentions(|f| f.empty_parse())
+                })
+        }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
+    } else {
+        command.create_interaction_response(&ctx.http, |response| {
+            response.kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message.content(format!("User <@{}> is not banned on this server", user_id))
+                        .allowed_mentions(|f| f.empty_parse())
+                })
+        }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
+    }
+}
+
+/// Kicks a member, recording the kick as a case and posting it to the mod log.
+pub async fn kick_user(db: &DatabaseConnection, ctx: &Context, server_id: GuildId, user_id: u64, moderator_id: Option<u64>, reason: String) -> Result<punishment::Model, RaincoatError> {
+    server_id.kick_with_reason(&ctx.http, user_id, &audit_log_reason(ctx, moderator_id, &Some(reason.clone())).await).await
+        .map_err(|err| RaincoatError { cause: format!("Unable to kick user: {}", err) })?;
+
+    let kicked_at = Utc::now().naive_utc();
     let new_punishment = punishment::ActiveModel {
         user_id: Set(user_id as i64),
         server_id: Set(server_id.0 as i64),
-        punishment_type: Set(PunishmentType::Ban),
-        expires: Set(punishment_expires),
+        punishment_type: Set(PunishmentType::Kick),
+        expires: Set(None),
+        moderator_id: Set(moderator_id.map(|id| id as i64)),
+        reason: Set(Some(reason)),
+        created_at: Set(kicked_at),
+        case_number: Set(next_case_number(db, server_id).await?),
+        // Kicks take effect immediately, so they never stay active
+        resolved_at: Set(Some(kicked_at)),
         ..Default::default()
     };
     let punishment_model: punishment::Model = new_punishment.insert(db)
         .await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
 
+    mod_log::post(db, &ctx.http, server_id, ModLogEntry::for_punishment("Kick", &punishment_model)).await;
+
+    Ok(punishment_model)
+}
+
+pub async fn create_kick_response(db: &DatabaseConnection, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<(), RaincoatError> {
+    let server_id = command.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
+
+    let mut user_id_opt: Option<u64> = None;
+    let mut reason_opt: Option<String> = None;
+    let mut dm = false;
+
+    for option in &command.data.options {
+        match option.name.as_str() {
+            "user" => {
+                if let ApplicationCommandInteractionDataOptionValue::User(user, _member) = &option.resolved.as_ref()
+                    .ok_or(RaincoatError { cause: "Couldn't resolve 'user' param".to_string() })? {
+                    user_id_opt = Some(user.id.0);
+                } else {
+                    return Err(RaincoatError { cause: "Unexpected type for 'user' param".to_string() })
+                }
+            }
+            "reason" => reason_opt = Some(parse_string_option("reason", option)?),
+            "dm" => {
+                if let ApplicationCommandInteractionDataOptionValue::Boolean(value) = &option.resolved.as_ref()
+                    .ok_or(RaincoatError { cause: "Couldn't resolve 'dm' param".to_string() })? {
+                    dm = *value;
+                } else {
+                    return Err(RaincoatError { cause: "Unexpected type for 'dm' param".to_string() })
+                }
+            }
+            unknown => {
+                return Err(RaincoatError { cause: format!("Unknown param: {}", unknown)})
+            }
+        }
+    }
+
+    let user_id = user_id_opt.ok_or(RaincoatError { cause: "Requires 'user' param".to_string() })?;
+    let reason = reason_opt.ok_or(RaincoatError { cause: "Requires 'reason' param".to_string() })?;
+
     let member = ctx.cache.member(server_id, user_id).await
-        .ok_or(RaincoatError { cause: format!("Unable to fetch information about user") })?;
-    let roles = &member.roles;
+        .ok_or(RaincoatError { cause: "Unable to fetch information about user".to_string() })?;
 
-    for role_id in roles {
-        let new_punishment_removed_role = punishment_removed_role::ActiveModel {
-            punishment_id: Set(punishment_model.id),
-            role_id: Set(role_id.0 as i64),
-            ..Default::default()
-        };
-        new_punishment_removed_role.insert(db)
-            .await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+    // The DM has to go out before the kick, since we can no longer message users who share no servers with us
+    let mut dm_failed = false;
+    if dm {
+        let server_name = server_id.name(&ctx.cache).await
+            .unwrap_or_else(|| "the server".to_string());
+        if let Err(err) = member.user.direct_message(&ctx.http, |m| {
+            m.content(format!("You have been kicked from {} for: {}", server_name, reason))
+        }).await {
+            eprintln!("Failed to DM user {} before kicking: {}", user_id, err);
+            dm_failed = true;
+        }
     }
 
-    member.ban(&ctx.http, 0).await
+    let punishment_model = kick_user(db, ctx, server_id, user_id, Some(command.user.id.0), reason).await?;
+
+    command.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                if dm_failed {
+                    message.content(format!("Kicked {} (unable to DM user)\n{}", member.mention(), punishment_details(&punishment_model)))
+                } else {
+                    message.content(format!("Kicked {}\n{}", member.mention(), punishment_details(&punishment_model)))
+                }.allowed_mentions(|f| f.empty_parse())
+            })
+    }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
+}
+
+pub async fn create_softban_response(db: &DatabaseConnection, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<(), RaincoatError> {
+    let server_id = command.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
+
+    let mut user_id_opt: Option<u64> = None;
+    let mut reason_opt: Option<String> = None;
+    let mut delete_message_days: u8 = 1;
+
+    for option in &command.data.options {
+        match option.name.as_str() {
+            "user" => {
+                if let ApplicationCommandInteractionDataOptionValue::User(user, _member) = &option.resolved.as_ref()
+                    .ok_or(RaincoatError { cause: "Couldn't resolve 'user' param".to_string() })? {
+                    user_id_opt = Some(user.id.0);
+                } else {
+                    return Err(RaincoatError { cause: "Unexpected type for 'user' param".to_string() })
+                }
+            }
+            "reason" => reason_opt = Some(parse_string_option("reason", option)?),
+            "delete_messages" => delete_message_days = parse_delete_message_days(option)?,
+            unknown => {
+                return Err(RaincoatError { cause: format!("Unknown param: {}", unknown)})
+            }
+        }
+    }
+
+    let user_id = user_id_opt.ok_or(RaincoatError { cause: "Requires 'user' param".to_string() })?;
+
+    // Softbanning someone who is already banned would lift their ban
+    let bans = server_id.bans(&ctx.http).await
+        .map_err(|err| RaincoatError { cause: format!("Unable to fetch bans: {}", err) })?;
+    if bans.iter().any(|ban| ban.user.id.0 == user_id) {
+        return Err(RaincoatError { cause: "User is already banned; softbanning them would lift the ban.".to_string() })
+    }
+
+    let audit_reason = audit_log_reason(ctx, Some(command.user.id.0), &reason_opt).await;
+    server_id.ban_with_reason(&ctx.http, user_id, delete_message_days, &audit_reason).await
         .map_err(|err| RaincoatError { cause: format!("Unable to ban user: {}", err) })?;
 
+    // Record the case before lifting the ban, so a failed unban still leaves a trace of what happened
+    let softbanned_at = Utc::now().naive_utc();
+    let new_punishment = punishment::ActiveModel {
+        user_id: Set(user_id as i64),
+        server_id: Set(server_id.0 as i64),
+        punishment_type: Set(PunishmentType::Softban),
+        expires: Set(None),
+        moderator_id: Set(Some(command.user.id.0 as i64)),
+        reason: Set(reason_opt),
+        created_at: Set(softbanned_at),
+        case_number: Set(next_case_number(db, server_id).await?),
+        // Softbans are lifted straight away, so they never stay active
+        resolved_at: Set(Some(softbanned_at)),
+        ..Default::default()
+    };
+    let punishment_model: punishment::Model = new_punishment.insert(db)
+        .await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+    mod_log::post(db, &ctx.http, server_id, ModLogEntry::for_punishment("Softban", &punishment_model)).await;
+
+    let mut content = format!("Softbanned {}, deleting {} day(s) of messages\n{}", UserId(user_id).mention(), delete_message_days, punishment_details(&punishment_model));
+    if let Err(err) = ctx.http.remove_ban(server_id.0, user_id).await {
+        let warning = format!("Failed to lift the ban for {} after softbanning them (case #{}): {}\nThey are still banned; use /unban to let them rejoin.", UserId(user_id).mention(), punishment_model.case_number, err);
+        mod_log::post_notice(db, &ctx.http, server_id, "Softban unban failed", warning.clone()).await;
+        content = format!("{}\n\n{}", content, warning);
+    }
+
     command.create_interaction_response(&ctx.http, |response| {
         response.kind(InteractionResponseType::ChannelMessageWithSource)
             .interaction_response_data(|message| {
-                match punishment_expires {
-                    Some(expires) => {
-                        message.content(format!("Banned {} until <t:{}>", member.mention().to_string(), expires.timestamp()))
-                            .allowed_mentions(|f| f.empty_parse())
-                    }
-                    None => {
-                        message.content(format!("Banned {} indefinitely", member.mention().to_string()))
-                            .allowed_mentions(|f| f.empty_parse())
-                    }
+                message.content(content)
+                    .allowed_mentions(|f| f.empty_parse())
+            })
+    }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
+}
+
+pub async fn create_timeout_response(db: &DatabaseConnection, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<(), RaincoatError> {
+    let server_id = command.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
+
+    let mut user_id_opt: Option<u64> = None;
+    let mut reason_opt: Option<String> = None;
+    let mut time_accumulator: Duration = Duration::zero();
+
+    for option in &command.data.options {
+        match option.name.as_str() {
+            "user" => {
+                if let ApplicationCommandInteractionDataOptionValue::User(user, _member) = &option.resolved.as_ref()
+                    .ok_or(RaincoatError { cause: "Couldn't resolve 'user' param".to_string() })? {
+                    user_id_opt = Some(user.id.0);
+                } else {
+                    return Err(RaincoatError { cause: "Unexpected type for 'user' param".to_string() })
                 }
+            }
+            "reason" => reason_opt = Some(parse_string_option("reason", option)?),
+            other => duration_parse(&mut time_accumulator, other, option)?
+        }
+    };
+
+    let user_id = user_id_opt.ok_or(RaincoatError { cause: "Requires 'user' param".to_string() })?;
+    let duration = check_timeout_duration(if time_accumulator == Duration::zero() {
+        None
+    } else {
+        Some(time_accumulator)
+    })?;
+    let punishment_expires = expires_after(duration)?;
+
+    let punishment_model = timeout_user(db, ctx, server_id, user_id, Some(command.user.id.0), reason_opt, punishment_expires).await?;
+
+    command.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                message.content(format!("Timed out {} until <t:{}>\n{}", UserId(user_id).mention(), punishment_expires.timestamp(), punishment_details(&punishment_model)))
+                    .allowed_mentions(|f| f.empty_parse())
             })
     }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
 }
 
-pub async fn create_unban_response(db: &DatabaseConnection, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<(), RaincoatError> {
+pub async fn create_untimeout_response(db: &DatabaseConnection, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<(), RaincoatError> {
     let server_id = command.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
 
     let mut user_id_opt: Option<u64> = None;
+    let mut reason_opt: Option<String> = None;
 
     for option in &command.data.options {
         match option.name.as_str() {
@@ -374,6 +1000,7 @@ pub async fn create_unban_response(db: &DatabaseConnection, ctx: &Context, comma
                     return Err(RaincoatError { cause: "Unexpected type for 'user' param".to_string() })
                 }
             }
+            "reason" => reason_opt = Some(parse_string_option("reason", option)?),
             unknown => {
                 return Err(RaincoatError { cause: format!("Unknown param: {}", unknown)})
             }
@@ -382,32 +1009,37 @@ pub async fn create_unban_response(db: &DatabaseConnection, ctx: &Context, comma
 
     let user_id = user_id_opt.ok_or(RaincoatError { cause: "Requires 'user' param".to_string() })?;
 
-    let user_bans: Vec<(punishment::Model, Vec<punishment_removed_role::Model>)> = punishment::Entity::find()
-        .filter(punishment::Column::PunishmentType.eq(PunishmentType::Ban))
+    let user_timeouts: Vec<punishment::Model> = punishment::Entity::find()
+        .filter(punishment::Column::PunishmentType.eq(PunishmentType::Timeout))
         .filter(punishment::Column::UserId.eq(user_id as i64))
         .filter(punishment::Column::ServerId.eq(server_id.0 as i64))
-        .find_with_related(punishment_removed_role::Entity)
+        .filter(punishment::Column::ResolvedAt.is_null())
         .all(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
 
-    if user_bans.len() > 0 {
-        for (ban, roles) in user_bans {
-            ban.delete(db).await
-                .map_err(|err| RaincoatError { cause: format!("{}", err) })?;
-
-            for role in roles {
-                // TODO: figure out some way to automatically readd roles?
-                role.delete(db).await
-                    .map_err(|err| RaincoatError { cause: format!("{}", err) })?;
-            }
-        };
+    if !user_timeouts.is_empty() {
+        server_id.edit_member(&ctx.http, user_id, |member| member.enable_communication()).await
+            .map_err(|err| RaincoatError { cause: format!("Unable to remove timeout: {}", err) })?;
 
-        ctx.http.remove_ban(server_id.0, user_id).await
-            .map_err(|err| RaincoatError { cause: format!("Unable to unban user: {}", err) })?;
+        for timeout in user_timeouts {
+            let resolved = resolve_punishment(db, timeout, Some(command.user.id.0), reason_opt.clone()).await?;
+            mod_log::post(db, &ctx.http, server_id, ModLogEntry {
+                action: "Untimeout".to_string(),
+                target_id: user_id,
+                moderator_id: Some(command.user.id.0),
+                case_number: Some(resolved.case_number),
+                duration: None,
+                reason: reason_opt.clone()
+            }).await;
+        }
+        scheduler::wake(ctx).await;
 
         command.create_interaction_response(&ctx.http, |response| {
             response.kind(InteractionResponseType::ChannelMessageWithSource)
                 .interaction_response_data(|message| {
-                    message.content(format!("Unbanned <@{}>", user_id))
+                    match &reason_opt {
+                        Some(reason) => message.content(format!("Removed timeout from <@{}>\nReason: {}", user_id, reason)),
+                        None => message.content(format!("Removed timeout from <@{}>", user_id))
+                    }
                         .allowed_mentions(|f| f.empty_parse())
                 })
         }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
@@ -415,9 +1047,71 @@ pub async fn create_unban_response(db: &DatabaseConnection, ctx: &Context, comma
         command.create_interaction_response(&ctx.http, |response| {
             response.kind(InteractionResponseType::ChannelMessageWithSource)
                 .interaction_response_data(|message| {
-                    message.content(format!("User <@{}> is not banned on this server", user_id))
+                    message.content(format!("User <@{}> is not timed out on this server", user_id))
                         .allowed_mentions(|f| f.empty_parse())
                 })
         }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
     }
 }
+
+pub async fn create_role_restore_response(db: &DatabaseConnection, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<(), RaincoatError> {
+    let server_id = command.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
+
+    let mut hours_opt: Option<i64> = None;
+
+    for option in &command.data.options {
+        match option.name.as_str() {
+            "hours" => hours_opt = Some(parse_integer_option("hours", option)?),
+            unknown => {
+                return Err(RaincoatError { cause: format!("Unknown param: {}", unknown)})
+            }
+        }
+    }
+
+    let hours = hours_opt.ok_or(RaincoatError { cause: "Requires 'hours' param".to_string() })?;
+
+    let new_server = server::ActiveModel {
+        id: Set(server_id.0 as i64),
+        ban_role_restore_hours: Set(hours),
+        ..Default::default()
+    };
+    new_server.update(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+    command.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                if hours == 0 {
+                    message.content("Roles will no longer be restored to users who rejoin after a ban.")
+                } else {
+                    message.content(format!("Roles will be restored to users who rejoin within {} hours of a ban ending.", hours))
+                }
+            })
+    }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use super::{check_timeout_duration, checked_duration};
+
+    #[test]
+    fn timeouts_need_a_positive_duration() {
+        assert!(check_timeout_duration(None).is_err());
+        assert!(check_timeout_duration(Some(Duration::zero())).is_err());
+        assert!(check_timeout_duration(Some(Duration::minutes(-5))).is_err());
+    }
+
+    #[test]
+    fn timeouts_last_at_most_28_days() {
+        assert_eq!(check_timeout_duration(Some(Duration::days(28))).unwrap(), Duration::days(28));
+        assert!(check_timeout_duration(Some(Duration::days(28) + Duration::minutes(1))).is_err());
+        assert_eq!(check_timeout_duration(Some(Duration::minutes(1))).unwrap(), Duration::minutes(1));
+    }
+
+    #[test]
+    fn huge_durations_are_rejected_instead_of_overflowing() {
+        assert_eq!(checked_duration(2, 60 * 60), Some(Duration::hours(2)));
+        assert_eq!(checked_duration(i64::MAX, 4 * 12 * 7 * 24 * 60 * 60), None);
+        assert_eq!(checked_duration(i64::MAX / 60, 60), None);
+    }
+}
@@ -0,0 +1,320 @@
+use sea_orm::ActiveValue::Set;
+use sea_orm::{DatabaseConnection, EntityTrait, ActiveModelTrait, QueryFilter, QueryOrder, ColumnTrait, ModelTrait};
+use serenity::builder::{CreateApplicationCommandPermissions, CreateApplicationCommands};
+use serenity::model::channel::{ChannelType, Reaction, ReactionType};
+use serenity::model::id::{ChannelId, GuildId, RoleId};
+use serenity::model::interactions::application_command::{ApplicationCommandInteraction, ApplicationCommandInteractionDataOptionValue, ApplicationCommandOptionType, ApplicationCommandPermissionType};
+use serenity::model::interactions::InteractionResponseType;
+use serenity::prelude::Context;
+use crate::error::RaincoatError;
+use crate::model::reaction_role;
+use crate::model::reaction_role::ReactionRoleMode;
+use super::{punishments, verification};
+
+pub fn create_command(commands: &mut CreateApplicationCommands) {
+    commands.create_application_command(|command| {
+        command.name("reactionrole")
+            .description("Give out roles when members react to a message")
+            .default_permission(false)
+            .create_option(|option| {
+                option.name("add")
+                    .description("Give out a role when members react to a message with an emoji")
+                    .kind(ApplicationCommandOptionType::SubCommand)
+                    .create_sub_option(|suboption| {
+                        suboption.name("message")
+                            .description("The ID of the message to react to")
+                            .kind(ApplicationCommandOptionType::String)
+                            .required(true)
+                    })
+                    .create_sub_option(|suboption| {
+                        suboption.name("emoji")
+                            .description("The emoji to react with")
+                            .kind(ApplicationCommandOptionType::String)
+                            .required(true)
+                    })
+                    .create_sub_option(|suboption| {
+                        suboption.name("role")
+                            .description("The role to give out")
+                            .kind(ApplicationCommandOptionType::Role)
+                            .required(true)
+                    })
+                    .create_sub_option(|suboption| {
+                        suboption.name("mode")
+                            .description("How reacting and unreacting changes the role (default toggle)")
+                            .kind(ApplicationCommandOptionType::String)
+                            .add_string_choice("Toggle: add on react, remove on unreact", "toggle")
+                            .add_string_choice("Add only: add on react, keep on unreact", "add_only")
+                            .add_string_choice("Unique: only one unique role from this message at a time", "unique")
+                            .required(false)
+                    })
+                    .create_sub_option(|suboption| {
+                        suboption.name("channel")
+                            .description("The channel the message is in, if it isn't this one")
+                            .kind(ApplicationCommandOptionType::Channel)
+                            .channel_types(&[ChannelType::Text])
+                            .required(false)
+                    })
+            })
+            .create_option(|option| {
+                option.name("remove")
+                    .description("Stop giving out a role for reacting to a message")
+                    .kind(ApplicationCommandOptionType::SubCommand)
+                    .create_sub_option(|suboption| {
+                        suboption.name("message")
+                            .description("The ID of the message")
+                            .kind(ApplicationCommandOptionType::String)
+                            .required(true)
+                    })
+                    .create_sub_option(|suboption| {
+                        suboption.name("emoji")
+                            .description("The emoji of the reaction role to remove")
+                            .kind(ApplicationCommandOptionType::String)
+                            .required(true)
+                    })
+            })
+            .create_option(|option| {
+                option.name("list")
+                    .description("List the reaction roles on this server")
+                    .kind(ApplicationCommandOptionType::SubCommand)
+            })
+    });
+}
+
+pub fn create_permissions(mod_role: u64, updater: &mut CreateApplicationCommandPermissions) -> &mut CreateApplicationCommandPermissions {
+    updater.create_permissions(|permissions| {
+        permissions.kind(ApplicationCommandPermissionType::Role)
+            .id(mod_role)
+            .permission(true)
+    })
+}
+
+fn mode_name(mode: &ReactionRoleMode) -> &'static str {
+    match mode {
+        ReactionRoleMode::Toggle => "toggle",
+        ReactionRoleMode::AddOnly => "add only",
+        ReactionRoleMode::Unique => "unique"
+    }
+}
+
+fn parse_message_id(value: &str) -> Result<u64, RaincoatError> {
+    value.parse()
+        .map_err(|_err| RaincoatError { cause: format!("Couldn't parse {} as message id", value) })
+}
+
+pub async fn create_response(db: &DatabaseConnection, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<(), RaincoatError> {
+    let server_id = command.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
+
+    let subcommand = command.data.options.first().ok_or(RaincoatError { cause: "Command target is required.".to_string() })?;
+
+    let content = match subcommand.name.as_str() {
+        "add" => {
+            let mut message_id_opt: Option<u64> = None;
+            let mut emoji_opt: Option<String> = None;
+            let mut role_id_opt: Option<u64> = None;
+            let mut mode = ReactionRoleMode::Toggle;
+            let mut channel_id = command.channel_id.0;
+
+            for option in &subcommand.options {
+                match option.name.as_str() {
+                    "message" => message_id_opt = Some(parse_message_id(&punishments::parse_string_option("message", option)?)?),
+                    "emoji" => emoji_opt = Some(punishments::parse_string_option("emoji", option)?),
+                    "role" => {
+                        if let ApplicationCommandInteractionDataOptionValue::Role(role) = &option.resolved.as_ref()
+                            .ok_or(RaincoatError { cause: "Couldn't resolve 'role' param".to_string() })? {
+                            role_id_opt = Some(role.id.0);
+                        } else {
+                            return Err(RaincoatError { cause: "Unexpected type for 'role' param".to_string() });
+                        }
+                    }
+                    "mode" => {
+                        mode = match punishments::parse_string_option("mode", option)?.as_str() {
+                            "toggle" => ReactionRoleMode::Toggle,
+                            "add_only" => ReactionRoleMode::AddOnly,
+                            "unique" => ReactionRoleMode::Unique,
+                            other => return Err(RaincoatError { cause: format!("Unknown mode: {}", other) })
+                        };
+                    }
+                    "channel" => {
+                        if let ApplicationCommandInteractionDataOptionValue::Channel(channel) = &option.resolved.as_ref()
+                            .ok_or(RaincoatError { cause: "Couldn't resolve 'channel' param".to_string() })? {
+                            channel_id = channel.id.0;
+                        } else {
+                            return Err(RaincoatError { cause: "Unexpected type for 'channel' param".to_string() });
+                        }
+                    }
+                    unknown => return Err(RaincoatError { cause: format!("Unknown parameter: {}", unknown) })
+                }
+            }
+
+            let message_id = message_id_opt.ok_or(RaincoatError { cause: "Requires 'message' param".to_string() })?;
+            let emoji = emoji_opt.ok_or(RaincoatError { cause: "Requires 'emoji' param".to_string() })?;
+            let role_id = role_id_opt.ok_or(RaincoatError { cause: "Requires 'role' param".to_string() })?;
+
+            // Check everything up front, so the mod can fix every problem at once rather than finding them one by one
+            let problems = verification::validate_reaction_target(ctx, server_id, channel_id, message_id, &emoji, RoleId(role_id)).await;
+            if !problems.is_empty() {
+                return Err(RaincoatError { cause: format!("The reaction role was not added:\n- {}", problems.join("\n- ")) });
+            }
+
+            let existing = reaction_role::Entity::find()
+                .filter(reaction_role::Column::ServerId.eq(server_id.0 as i64))
+                .filter(reaction_role::Column::MessageId.eq(message_id as i64))
+                .filter(reaction_role::Column::Emoji.eq(emoji.clone()))
+                .one(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+            let mode_description = mode_name(&mode);
+            match existing {
+                Some(existing) => {
+                    let mut updated: reaction_role::ActiveModel = existing.into();
+                    updated.role_id = Set(role_id as i64);
+                    updated.mode = Set(mode);
+                    updated.update(db).await
+                }
+                None => {
+                    reaction_role::ActiveModel {
+                        server_id: Set(server_id.0 as i64),
+                        channel_id: Set(channel_id as i64),
+                        message_id: Set(message_id as i64),
+                        emoji: Set(emoji.clone()),
+                        role_id: Set(role_id as i64),
+                        mode: Set(mode),
+                        ..Default::default()
+                    }.insert(db).await
+                }
+            }.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+            format!("Reacting with {} will now give <@&{}> ({}).", emoji, role_id, mode_description)
+        }
+        "remove" => {
+            let mut message_id_opt: Option<u64> = None;
+            let mut emoji_opt: Option<String> = None;
+
+            for option in &subcommand.options {
+                match option.name.as_str() {
+                    "message" => message_id_opt = Some(parse_message_id(&punishments::parse_string_option("message", option)?)?),
+                    "emoji" => emoji_opt = Some(punishments::parse_string_option("emoji", option)?),
+                    unknown => return Err(RaincoatError { cause: format!("Unknown parameter: {}", unknown) })
+                }
+            }
+
+            let message_id = message_id_opt.ok_or(RaincoatError { cause: "Requires 'message' param".to_string() })?;
+            let emoji = emoji_opt.ok_or(RaincoatError { cause: "Requires 'emoji' param".to_string() })?;
+
+            let existing = reaction_role::Entity::find()
+                .filter(reaction_role::Column::ServerId.eq(server_id.0 as i64))
+                .filter(reaction_role::Column::MessageId.eq(message_id as i64))
+                .filter(reaction_role::Column::Emoji.eq(emoji.clone()))
+                .one(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?
+                .ok_or(RaincoatError { cause: format!("There is no reaction role for {} on message {}", emoji, message_id) })?;
+
+            // Take our own reaction back off, so members aren't led to react for a role that no longer exists
+            if let Ok(reaction_type) = ReactionType::try_from(emoji.clone()) {
+                let bot_id = ctx.cache.current_user_id().await;
+                if let Err(err) = ChannelId(existing.channel_id as u64).delete_reaction(&ctx.http, message_id, Some(bot_id), reaction_type).await {
+                    eprintln!("Failed to remove reaction role reaction in server {}: {}", server_id.0, err);
+                }
+            }
+            existing.delete(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+            format!("Removed the reaction role for {}.", emoji)
+        }
+        "list" => {
+            let reaction_roles: Vec<reaction_role::Model> = reaction_role::Entity::find()
+                .filter(reaction_role::Column::ServerId.eq(server_id.0 as i64))
+                .order_by_asc(reaction_role::Column::MessageId)
+                .all(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+            if reaction_roles.is_empty() {
+                "There are no reaction roles on this server.".to_string()
+            } else {
+                reaction_roles.iter()
+                    .map(|reaction_role| format!("https://discord.com/channels/{}/{}/{} {} → <@&{}> ({})",
+                                                 server_id.0, reaction_role.channel_id, reaction_role.message_id,
+                                                 reaction_role.emoji, reaction_role.role_id, mode_name(&reaction_role.mode)))
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            }
+        }
+        unknown => return Err(RaincoatError { cause: format!("Unknown subcommand: {}", unknown) })
+    };
+
+    command.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                message.content(content)
+                    .allowed_mentions(|f| f.empty_parse())
+            })
+    }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
+}
+
+/// Finds the reaction roles on the message a reaction was made on, along with the one for its emoji if there is one.
+async fn find_reaction_roles(db: &DatabaseConnection, reaction: &Reaction) -> Result<(Vec<reaction_role::Model>, Option<reaction_role::Model>), RaincoatError> {
+    let reaction_roles: Vec<reaction_role::Model> = reaction_role::Entity::find()
+        .filter(reaction_role::Column::MessageId.eq(reaction.message_id.0 as i64))
+        .all(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+    let matching = reaction_roles.iter()
+        .find(|reaction_role| ReactionType::try_from(reaction_role.emoji.clone())
+            .is_ok_and(|reaction_type| reaction_type == reaction.emoji))
+        .cloned();
+
+    Ok((reaction_roles, matching))
+}
+
+/// Gives out the role for a reaction, taking away the member's other unique roles from the message if needed.
+pub async fn handle_reaction_add(db: &DatabaseConnection, ctx: &Context, reaction: &Reaction) -> Result<(), RaincoatError> {
+    let (server_id, user_id) = match (reaction.guild_id, reaction.user_id) {
+        (Some(server_id), Some(user_id)) => (server_id, user_id),
+        _ => return Ok(())
+    };
+    if user_id == ctx.cache.current_user_id().await {
+        return Ok(());
+    }
+
+    let (reaction_roles, matching) = find_reaction_roles(db, reaction).await?;
+    let reaction_role = match matching {
+        Some(reaction_role) => reaction_role,
+        None => return Ok(())
+    };
+
+    ctx.http.add_member_role(server_id.0, user_id.0, reaction_role.role_id as u64).await
+        .map_err(|err| RaincoatError { cause: format!("Failed to add reaction role in server {}: {}", server_id.0, err) })?;
+
+    if reaction_role.mode == ReactionRoleMode::Unique {
+        for other in reaction_roles.iter().filter(|other| other.mode == ReactionRoleMode::Unique && other.id != reaction_role.id) {
+            remove_role(ctx, server_id, user_id.0, other.role_id as u64).await;
+
+            // Clearing their other reaction keeps the message showing which role they picked
+            if let Ok(reaction_type) = ReactionType::try_from(other.emoji.clone()) {
+                if let Err(err) = reaction.channel_id.delete_reaction(&ctx.http, reaction.message_id, Some(user_id), reaction_type).await {
+                    eprintln!("Failed to remove reaction in server {}: {}", server_id.0, err);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Takes away the role for a withdrawn reaction, unless it is only given out.
+pub async fn handle_reaction_remove(db: &DatabaseConnection, ctx: &Context, reaction: &Reaction) -> Result<(), RaincoatError> {
+    let (server_id, user_id) = match (reaction.guild_id, reaction.user_id) {
+        (Some(server_id), Some(user_id)) => (server_id, user_id),
+        _ => return Ok(())
+    };
+    if user_id == ctx.cache.current_user_id().await {
+        return Ok(());
+    }
+
+    let (_reaction_roles, matching) = find_reaction_roles(db, reaction).await?;
+    if let Some(reaction_role) = matching.filter(|reaction_role| reaction_role.mode != ReactionRoleMode::AddOnly) {
+        remove_role(ctx, server_id, user_id.0, reaction_role.role_id as u64).await;
+    }
+
+    Ok(())
+}
+
+async fn remove_role(ctx: &Context, server_id: GuildId, user_id: u64, role_id: u64) {
+    if let Err(err) = ctx.http.remove_member_role(server_id.0, user_id, role_id).await {
+        eprintln!("Failed to remove reaction role in server {}: {}", server_id.0, err);
+    }
+}
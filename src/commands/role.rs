@@ -1,15 +1,27 @@
-use sea_orm::{DatabaseConnection, EntityTrait, QueryFilter, ColumnTrait};
+use std::collections::HashSet;
 
-use serenity::builder::{CreateActionRow, CreateApplicationCommands, CreateSelectMenu, CreateSelectMenuOption};
-use serenity::model::id::{GuildId, RoleId, UserId};
+use chrono::Duration;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{DatabaseConnection, EntityTrait, ActiveModelTrait, QueryFilter, QueryOrder, ColumnTrait, ModelTrait};
+
+use serenity::builder::{CreateActionRow, CreateApplicationCommands, CreateEmbed, CreateSelectMenu, CreateSelectMenuOption};
+use serenity::model::channel::MessageFlags;
+use serenity::model::guild::Member;
+use serenity::model::id::{ChannelId, GuildId, RoleId};
 use serenity::model::interactions::application_command::ApplicationCommandInteraction;
 use serenity::model::interactions::InteractionResponseType;
+use serenity::model::interactions::message_component::ButtonStyle;
 use serenity::model::prelude::InteractionApplicationCommandCallbackDataFlags;
-use serenity::model::prelude::message_component::MessageComponentInteraction;
+use serenity::model::prelude::message_component::{ActionRowComponent, MessageComponentInteraction};
 use serenity::prelude::*;
 
-use crate::error::RaincoatError;
+use crate::error::{is_not_found, RaincoatError};
 use crate::model::optional_role;
+use crate::model::role_group;
+use crate::model::role_menu;
+use crate::model::temporary_role;
+use crate::scheduler;
+use super::punishments;
 
 pub fn create_command(commands: &mut CreateApplicationCommands) {
     commands.create_application_command(|command| {
@@ -18,46 +30,185 @@ pub fn create_command(commands: &mut CreateApplicationCommands) {
     });
 }
 
+// Discord allows 25 options per select menu, and 5 rows per message with one kept for page buttons
+const ROLES_PER_MENU: usize = 25;
+const MENUS_PER_PAGE: usize = 4;
+const MAX_FIELD_LENGTH: usize = 1024;
+
+/// A set of optional roles shown in its own select menus.
+struct RoleMenuGroup {
+    // "other" for roles which aren't in a group
+    key: String,
+    name: String,
+    min_values: u64,
+    max_values: u64,
+    roles: Vec<optional_role::Model>
+}
+
+/// Parses a list of roles given as IDs or mentions, separated by commas or spaces.
+pub fn parse_role_list(value: &str) -> Vec<RoleId> {
+    value.split(|c: char| c == ',' || c.is_whitespace())
+        .map(|part| part.trim_start_matches("<@&").trim_end_matches('>'))
+        .filter_map(|part| part.parse().ok())
+        .map(RoleId)
+        .collect()
+}
+
+/// Whether a member with the given roles meets an optional role's prerequisites and exclusions.
+fn is_eligible(role: &optional_role::Model, held: &[RoleId]) -> bool {
+    let required = role.required_role_ids.as_deref().map(parse_role_list).unwrap_or_default();
+    let excluded = role.excluded_role_ids.as_deref().map(parse_role_list).unwrap_or_default();
+
+    (required.is_empty() || required.iter().any(|id| held.contains(id)))
+        && !excluded.iter().any(|id| held.contains(id))
+}
+
+/// Splits a server's optional roles into their groups, with ungrouped roles last.
+///
+/// When there is a member, roles they can't pick are left out, unless they already have them and may want to remove them.
+/// Roles which have been deleted from Discord are skipped until they are cleaned up.
+async fn role_groups(db: &DatabaseConnection, ctx: &Context, server_id: GuildId, user: Option<&Member>) -> Result<Vec<RoleMenuGroup>, RaincoatError> {
+    let groups: Vec<role_group::Model> = role_group::Entity::find()
+        .filter(role_group::Column::ServerId.eq(server_id.0 as i64))
+        .order_by_asc(role_group::Column::Id)
+        .all(db).await
+        .map_err(|err| RaincoatError { cause: format!("DB Error: {}", err)})?;
+    let optional_roles: Vec<optional_role::Model> = optional_role::Entity::find()
+        .filter(<optional_role::Entity as EntityTrait>::Column::ServerId.eq(server_id.0))
+        .all(db).await
+        .map_err(|err| RaincoatError { cause: format!("DB Error: {}", err)})?
+        .into_iter()
+        .filter(|role| user.is_none_or(|user| user.roles.contains(&RoleId(role.role_id as u64)) || is_eligible(role, &user.roles)))
+        .collect();
+    let mut existing_roles = Vec::with_capacity(optional_roles.len());
+    for role in optional_roles {
+        if ctx.cache.role(server_id, RoleId(role.role_id as u64)).await.is_some() {
+            existing_roles.push(role);
+        }
+    }
+    let optional_roles = existing_roles;
+
+    let mut menu_groups: Vec<RoleMenuGroup> = groups.into_iter()
+        .map(|group| RoleMenuGroup {
+            key: group.id.to_string(),
+            name: group.name,
+            min_values: group.min_values as u64,
+            max_values: group.max_values as u64,
+            roles: optional_roles.iter().filter(|role| role.group_id == Some(group.id)).cloned().collect()
+        })
+        .filter(|group| !group.roles.is_empty())
+        .collect();
+
+    let ungrouped: Vec<optional_role::Model> = optional_roles.into_iter()
+        .filter(|role| role.group_id.is_none())
+        .collect();
+    if !ungrouped.is_empty() {
+        menu_groups.push(RoleMenuGroup {
+            key: "other".to_string(),
+            name: "Other roles".to_string(),
+            min_values: 0,
+            max_values: ungrouped.len() as u64,
+            roles: ungrouped
+        });
+    }
+
+    Ok(menu_groups)
+}
+
+/// Up to one select menu's worth of roles from a group.
+struct RoleMenuChunk<'a> {
+    group: &'a RoleMenuGroup,
+    index: usize,
+    count: usize,
+    roles: &'a [optional_role::Model]
+}
+
+/// Splits every group into chunks small enough for a select menu.
+fn role_menu_chunks(groups: &[RoleMenuGroup]) -> Vec<RoleMenuChunk<'_>> {
+    groups.iter()
+        .flat_map(|group| {
+            let count = group.roles.len().div_ceil(ROLES_PER_MENU);
+            group.roles.chunks(ROLES_PER_MENU)
+                .enumerate()
+                .map(move |(index, roles)| RoleMenuChunk { group, index, count, roles })
+        })
+        .collect()
+}
+
 pub async fn create_response(db: &DatabaseConnection, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<(), RaincoatError> {
-    let action_row = role_action_row(db, ctx, command
-        .guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string()})?, command.user.id).await?;
+    let server_id = command.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string()})?;
+    let user = ctx.http.get_member(server_id.0, command.user.id.0).await
+        .map_err(|err| RaincoatError { cause: format!("Failed to collect existing user information: {}", err) })?;
+
+    let action_rows = role_page_rows(db, ctx, server_id, "select", 0, Some(&user)).await?;
+    if action_rows.is_empty() {
+        return Err(RaincoatError { cause: "No optional roles set for this server.".to_string() })
+    }
 
     command.create_interaction_response(&ctx.http, |response| {
         response.kind(InteractionResponseType::ChannelMessageWithSource)
             .interaction_response_data(|message| {
-                message.components(|c| c.add_action_row(action_row))
+                message.components(|c| c.set_action_rows(action_rows))
                     .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
             })
     }).await.map_err(|err| RaincoatError { cause: format!("Couldn't respond to command: {}", err)})
 }
 
-async fn role_action_row(db: &DatabaseConnection, ctx: &Context, server_id: GuildId, user_id: UserId) -> Result<CreateActionRow, RaincoatError> {
-    let mut row = CreateActionRow::default();
-    row.add_select_menu(role_select_menu(db, ctx, server_id, user_id).await?);
-    Ok(row)
-}
-
-async fn role_select_menu(db: &DatabaseConnection, ctx: &Context, server_id: GuildId, user_id: UserId) -> Result<CreateSelectMenu, RaincoatError> {
-    let mut menu = CreateSelectMenu::default();
+/// Builds one page of role menus, with buttons to move between pages when they don't all fit in one message.
+///
+/// `kind` is "select" for a member's own menus and "menu" for the shared ones from a posted role menu.
+async fn role_page_rows(db: &DatabaseConnection, ctx: &Context, server_id: GuildId, kind: &str, page: usize, user: Option<&Member>) -> Result<Vec<CreateActionRow>, RaincoatError> {
+    let groups = role_groups(db, ctx, server_id, user).await?;
+    let chunks = role_menu_chunks(&groups);
+    let page_count = chunks.len().div_ceil(MENUS_PER_PAGE);
+    let page = page.min(page_count.saturating_sub(1));
 
-    let optional_roles: Vec<optional_role::Model> = optional_role::Entity::find()
-        .filter(<optional_role::Entity as EntityTrait>::Column::ServerId.eq(server_id.0))
-        .all(db).await
-        .map_err(|err| RaincoatError { cause: format!("DB Error: {}", err)})?;
+    let mut rows = Vec::with_capacity(MENUS_PER_PAGE + 1);
+    for chunk in chunks.iter().skip(page * MENUS_PER_PAGE).take(MENUS_PER_PAGE) {
+        let mut row = CreateActionRow::default();
+        row.add_select_menu(role_select_menu(ctx, server_id, chunk, &format!("role_{}", kind), user).await?);
+        rows.push(row);
+    }
 
-    if optional_roles.len() == 0 {
-        return Err(RaincoatError { cause: format!("No optional roles set for this server.") })
+    if page_count > 1 {
+        let mut row = CreateActionRow::default();
+        row.create_button(|button| {
+            button.custom_id(format!("role_page:{}:{}", kind, page.saturating_sub(1)))
+                .label("Previous")
+                .style(ButtonStyle::Secondary)
+                .disabled(page == 0)
+        })
+            .create_button(|button| {
+                button.custom_id("role_page:current")
+                    .label(format!("Page {}/{}", page + 1, page_count))
+                    .style(ButtonStyle::Secondary)
+                    .disabled(true)
+            })
+            .create_button(|button| {
+                button.custom_id(format!("role_page:{}:{}", kind, page + 1))
+                    .label("Next")
+                    .style(ButtonStyle::Secondary)
+                    .disabled(page + 1 >= page_count)
+            });
+        rows.push(row);
     }
 
+    Ok(rows)
+}
 
-    let user = ctx.http.get_member(server_id.0, user_id.0).await
-        .map_err(|err| RaincoatError { cause: format!("Failed to collect existing user information: {}", err) })?;
+/// Builds the menu for one chunk of a group's optional roles, preselecting the member's current roles if there is one.
+async fn role_select_menu(ctx: &Context, server_id: GuildId, chunk: &RoleMenuChunk<'_>, custom_id: &str, user: Option<&Member>) -> Result<CreateSelectMenu, RaincoatError> {
+    let mut menu = CreateSelectMenu::default();
+    let group = chunk.group;
 
-    let mut options = Vec::with_capacity(optional_roles.len());
+    let mut options = Vec::with_capacity(chunk.roles.len());
 
-    for role in &optional_roles {
-        let cached_role = ctx.cache.role(server_id, RoleId(role.role_id as u64)).await
-            .ok_or(RaincoatError { cause: format!("Role {} no longer exists.", role.role_id)})?;
+    for role in chunk.roles {
+        // Roles deleted since the menu's roles were gathered are skipped, like in `role_groups`
+        let cached_role = match ctx.cache.role(server_id, RoleId(role.role_id as u64)).await {
+            Some(cached_role) => cached_role,
+            None => continue
+        };
 
         let mut option = CreateSelectMenuOption::default();
         option.label(cached_role.name.clone());
@@ -69,14 +220,29 @@ async fn role_select_menu(db: &DatabaseConnection, ctx: &Context, server_id: Gui
         if let Some(description) = &role.description {
             option.description(description.clone());
         }
-        option.default_selection(user.roles.contains(&RoleId(role.role_id as u64)));
+        if let Some(user) = user {
+            option.default_selection(user.roles.contains(&RoleId(role.role_id as u64)));
+        }
         options.push(option);
     }
 
-    menu.custom_id("role_select");
-    menu.placeholder("Select optional roles");
-    menu.max_values(optional_roles.len() as u64);
-    menu.min_values(0);
+    let name = if chunk.count > 1 {
+        format!("{} ({}/{})", group.name, chunk.index + 1, chunk.count)
+    } else {
+        group.name.clone()
+    };
+    let max_values = group.max_values.min(chunk.roles.len() as u64);
+    menu.custom_id(format!("{}:{}:{}", custom_id, group.key, chunk.index));
+    if user.is_some() {
+        menu.placeholder(format!("Select {}", name));
+        // A group's minimum can only be checked across all of its menus, which happens when a choice is made
+        menu.min_values(if chunk.count > 1 { 0 } else { group.min_values.min(max_values) });
+    } else {
+        // Everyone shares the posted menu, so it can't show anyone's current roles and instead toggles whatever is picked
+        menu.placeholder(format!("Add or remove {}", name));
+        menu.min_values(1);
+    }
+    menu.max_values(max_values);
     menu.options(move |f| {
         for option in options {
             f.add_option(option);
@@ -87,34 +253,212 @@ async fn role_select_menu(db: &DatabaseConnection, ctx: &Context, server_id: Gui
     Ok(menu)
 }
 
+fn group_limits(group: &RoleMenuGroup) -> String {
+    let max_values = group.max_values.min(group.roles.len() as u64);
+    match (group.min_values, max_values) {
+        (0, max) if max == group.roles.len() as u64 => "pick any".to_string(),
+        (0, max) => format!("pick up to {}", max),
+        (min, max) if min == max => format!("pick {}", min),
+        (min, max) => format!("pick {} to {}", min, max)
+    }
+}
+
+/// Builds the embed and first page of menus for a posted role menu, leaving out the menus when there are no optional roles.
+async fn role_menu_message(db: &DatabaseConnection, ctx: &Context, server_id: GuildId) -> Result<(CreateEmbed, Vec<CreateActionRow>), RaincoatError> {
+    let groups = role_groups(db, ctx, server_id, None).await?;
+
+    let mut embed = CreateEmbed::default();
+    embed.title("Optional roles");
+    if groups.is_empty() {
+        embed.description("There are no optional roles on this server.");
+        return Ok((embed, Vec::new()));
+    }
+
+    embed.description("Pick roles below to add them, or pick roles you already have to remove them.");
+    for chunk in role_menu_chunks(&groups) {
+        // Descriptions are left to the menus, so that servers with lots of roles stay within Discord's embed limits
+        let mut value = String::new();
+        for role in chunk.roles {
+            let line = match &role.emoji {
+                Some(emoji) => format!("{} <@&{}>\n", emoji, role.role_id),
+                None => format!("<@&{}>\n", role.role_id)
+            };
+            if value.len() + line.len() > MAX_FIELD_LENGTH {
+                value.push('…');
+                break;
+            }
+            value.push_str(&line);
+        }
+
+        let mut name = format!("{} ({})", chunk.group.name, group_limits(chunk.group));
+        if chunk.count > 1 {
+            name = format!("{} {}/{}", name, chunk.index + 1, chunk.count);
+        }
+        embed.field(name, value, false);
+    }
+
+    let rows = role_page_rows(db, ctx, server_id, "menu", 0, None).await?;
+    Ok((embed, rows))
+}
+
+/// Posts a role menu anyone can use in a channel, which is kept up to date as optional roles change.
+pub async fn post_role_menu(db: &DatabaseConnection, ctx: &Context, server_id: GuildId, channel_id: ChannelId) -> Result<(), RaincoatError> {
+    let (embed, rows) = role_menu_message(db, ctx, server_id).await?;
+
+    let message = channel_id.send_message(&ctx.http, |m| {
+        m.set_embed(embed)
+            .components(|c| c.set_action_rows(rows))
+    }).await.map_err(|err| RaincoatError { cause: format!("Failed to post role menu: {}", err) })?;
+
+    role_menu::ActiveModel {
+        message_id: Set(message.id.0 as i64),
+        channel_id: Set(channel_id.0 as i64),
+        server_id: Set(server_id.0 as i64)
+    }.insert(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+    Ok(())
+}
+
+/// Edits every posted role menu in a server to match the current optional roles.
+///
+/// Menus whose message has been deleted are forgotten.
+pub async fn refresh_role_menus(db: &DatabaseConnection, ctx: &Context, server_id: GuildId) -> Result<(), RaincoatError> {
+    let menus: Vec<role_menu::Model> = role_menu::Entity::find()
+        .filter(role_menu::Column::ServerId.eq(server_id.0 as i64))
+        .all(db).await
+        .map_err(|err| RaincoatError { cause: format!("DB Error: {}", err)})?;
+    if menus.is_empty() {
+        return Ok(());
+    }
+
+    let (embed, rows) = role_menu_message(db, ctx, server_id).await?;
+
+    let mut failures = Vec::new();
+    for menu in menus {
+        let channel_id = ChannelId(menu.channel_id as u64);
+        let embed = embed.clone();
+        let rows = rows.clone();
+        let edited = channel_id.edit_message(&ctx.http, menu.message_id as u64, |m| {
+            m.set_embed(embed)
+                .components(|c| c.set_action_rows(rows))
+        }).await;
+
+        match edited {
+            Ok(_) => {}
+            // Only forget menus whose message or channel has been deleted, not ones hit by a passing outage
+            Err(err) if is_not_found(&err) => {
+                menu.delete(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+            }
+            Err(err) => failures.push(format!("<#{}>: {}", channel_id.0, err))
+        }
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(RaincoatError { cause: format!("Failed to update role menus in {}", failures.join(", ")) })
+    }
+}
+
 pub async fn create_component_response(db: &DatabaseConnection, ctx: &Context, command: &MessageComponentInteraction) -> Result<(), RaincoatError> {
     let server_id: GuildId = command.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
     let mut user = ctx.http.get_member(server_id.0, command.user.id.0).await
         .map_err(|err| RaincoatError { cause: format!("Failed to collect existing user information: {}", err) })?;
 
-    let optional_roles: Vec<optional_role::Model> = optional_role::Entity::find()
-        .filter(<optional_role::Entity as EntityTrait>::Column::ServerId.eq(server_id.0))
-        .all(db).await
-        .map_err(|err| RaincoatError { cause: format!("DB Error: {}", err)})?;
+    // The posted role menu toggles the picked roles, while the personal one lists every role the user wants
+    let mut parts = command.data.custom_id.split(':');
+    let toggle = parts.next() == Some("role_menu");
+    let key = parts.next()
+        .ok_or(RaincoatError { cause: "This role menu is out of date, please run /role again.".to_string() })?;
+
+    let group = role_groups(db, ctx, server_id, if toggle { None } else { Some(&user) }).await?
+        .into_iter()
+        .find(|group| group.key == key)
+        .ok_or(RaincoatError { cause: "This role menu is out of date, please run /role again.".to_string() })?;
+    // Diff against the options the menu actually showed, as the roles the user is eligible for may have changed since
+    let shown: Vec<RoleId> = command.message.components.iter()
+        .flat_map(|row| &row.components)
+        .find_map(|component| match component {
+            ActionRowComponent::SelectMenu(menu) if menu.custom_id.as_ref() == Some(&command.data.custom_id) => Some(&menu.options),
+            _ => None
+        })
+        .ok_or(RaincoatError { cause: "This role menu is out of date, please run /role again.".to_string() })?
+        .iter()
+        .filter_map(|option| option.value.parse().ok())
+        .map(RoleId)
+        .collect();
+
+    // Only the roles in this menu change, the rest of the group is kept as it is
+    let picked: HashSet<RoleId> = command.data.values.iter()
+        .filter_map(|value| value.parse().ok())
+        .map(RoleId)
+        .collect();
+    let mut wanted: Vec<RoleId> = group.roles.iter()
+        .map(|role| RoleId(role.role_id as u64))
+        .filter(|id| match (shown.contains(id), toggle) {
+            (false, _) => user.roles.contains(id),
+            (true, true) => picked.contains(id) != user.roles.contains(id),
+            (true, false) => picked.contains(id)
+        })
+        .collect();
+
+    // Picking a new role from a full group on the posted menu swaps out the roles the user already had
+    if toggle && wanted.len() as u64 > group.max_values {
+        let excess = wanted.len() - group.max_values as usize;
+        let replaced: Vec<RoleId> = wanted.iter()
+            .filter(|id| !picked.contains(id))
+            .take(excess)
+            .copied()
+            .collect();
+        wanted.retain(|id| !replaced.contains(id));
+    }
+    // The posted menu shows every role, so prerequisites and exclusions have to be checked here
+    let mut ineligible = Vec::new();
+    for role in &group.roles {
+        let id = RoleId(role.role_id as u64);
+        if wanted.contains(&id) && !user.roles.contains(&id) && !is_eligible(role, &user.roles) {
+            let name = ctx.cache.role(server_id, id).await
+                .map(|role| role.name)
+                .unwrap_or_else(|| role.role_id.to_string());
+            ineligible.push(name);
+        }
+    }
+    if !ineligible.is_empty() {
+        return Err(RaincoatError { cause: format!("You don't meet the requirements for: {}", ineligible.join(", ")) });
+    }
+
+    if wanted.len() as u64 > group.max_values {
+        return Err(RaincoatError { cause: format!("You can only have {} role(s) from {}.", group.max_values, group.name) });
+    }
+    if (wanted.len() as u64) < group.min_values {
+        return Err(RaincoatError { cause: format!("You must keep at least {} role(s) from {}.", group.min_values, group.name) });
+    }
 
     let mut added_role_ids = Vec::new();
     let mut added_role_names = Vec::new();
     let mut removed_role_ids = Vec::new();
     let mut removed_role_names = Vec::new();
 
-    for role in optional_roles {
+    let mut expiring_roles = Vec::new();
+
+    for role in &group.roles {
         let id = RoleId(role.role_id as u64);
-        let id_str = role.role_id.to_string();
         let name = ctx.cache.role(server_id, id).await
-            .ok_or(RaincoatError { cause: format!("Failed to fetch information about role")})?
+            .ok_or(RaincoatError { cause: "Failed to fetch information about role".to_string() })?
             .name;
 
-        // Remove/add based on role being present in component.data.values
-        let present = command.data.values.contains(&id_str);
+        let present = wanted.contains(&id);
 
         if present && !user.roles.contains(&id) {
             added_role_ids.push(id);
-            added_role_names.push(name);
+            match role.duration_minutes {
+                Some(duration_minutes) => {
+                    let expires = punishments::expires_after(Duration::minutes(duration_minutes))?;
+                    added_role_names.push(format!("{} (until <t:{}:R>)", name, expires.timestamp()));
+                    expiring_roles.push((id, expires));
+                }
+                None => added_role_names.push(name)
+            }
         } else if !present && user.roles.contains(&id) {
             removed_role_ids.push(id);
             removed_role_names.push(name);
@@ -126,6 +470,39 @@ pub async fn create_component_response(db: &DatabaseConnection, ctx: &Context, c
     user.remove_roles(&ctx.http, removed_role_ids.as_slice()).await
         .map_err(|err| RaincoatError { cause: format!("Could not remove roles: {}", err) })?;
 
+    // Temporary roles which were removed early no longer need to expire
+    if !removed_role_ids.is_empty() {
+        temporary_role::Entity::delete_many()
+            .filter(temporary_role::Column::ServerId.eq(server_id.0 as i64))
+            .filter(temporary_role::Column::UserId.eq(user.user.id.0 as i64))
+            .filter(temporary_role::Column::RoleId.is_in(removed_role_ids.iter().map(|id| id.0 as i64)))
+            .exec(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+    }
+    // Expiry times are kept in the database, so temporary roles still expire if the bot restarts in the meantime
+    if !expiring_roles.is_empty() {
+        for (id, expires) in expiring_roles {
+            let key = (server_id.0 as i64, user.user.id.0 as i64, id.0 as i64);
+            let existing = temporary_role::Entity::find_by_id(key).one(db).await
+                .map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+            match existing {
+                Some(existing) => {
+                    let mut updated: temporary_role::ActiveModel = existing.into();
+                    updated.expires = Set(expires);
+                    updated.update(db).await
+                }
+                None => {
+                    temporary_role::ActiveModel {
+                        server_id: Set(key.0),
+                        user_id: Set(key.1),
+                        role_id: Set(key.2),
+                        expires: Set(expires)
+                    }.insert(db).await
+                }
+            }.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+        }
+        scheduler::wake(ctx).await;
+    }
+
     let mut output_buffer = Vec::with_capacity(2);
 
     if !added_role_names.is_empty() {
@@ -147,3 +524,87 @@ pub async fn create_component_response(db: &DatabaseConnection, ctx: &Context, c
             })
     }).await.map_err(|err| RaincoatError { cause: format!("Failed to respond to component: {}", err) })
 }
+
+pub async fn create_page_response(db: &DatabaseConnection, ctx: &Context, component: &MessageComponentInteraction) -> Result<(), RaincoatError> {
+    let server_id: GuildId = component.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
+
+    let mut parts = component.data.custom_id.split(':').skip(1);
+    let kind = parts.next()
+        .filter(|kind| *kind == "select" || *kind == "menu")
+        .ok_or(RaincoatError { cause: "Malformed role page button".to_string() })?;
+    let page: usize = parts.next()
+        .and_then(|page| page.parse().ok())
+        .ok_or(RaincoatError { cause: "Malformed role page button".to_string() })?;
+
+    let action_rows = if kind == "select" {
+        let user = ctx.http.get_member(server_id.0, component.user.id.0).await
+            .map_err(|err| RaincoatError { cause: format!("Failed to collect existing user information: {}", err) })?;
+        role_page_rows(db, ctx, server_id, kind, page, Some(&user)).await?
+    } else {
+        role_page_rows(db, ctx, server_id, kind, page, None).await?
+    };
+
+    // Paging from a posted role menu gives the member their own copy, so it doesn't change for everyone else
+    let ephemeral = component.message.flags
+        .is_some_and(|flags| flags.contains(MessageFlags::EPHEMERAL));
+
+    component.create_interaction_response(&ctx.http, |response| {
+        if ephemeral {
+            response.kind(InteractionResponseType::UpdateMessage)
+        } else {
+            response.kind(InteractionResponseType::ChannelMessageWithSource)
+        }.interaction_response_data(|message| {
+            message.components(|c| c.set_action_rows(action_rows))
+                .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+        })
+    }).await.map_err(|err| RaincoatError { cause: format!("Failed to respond to component: {}", err) })
+}
+
+#[cfg(test)]
+mod tests {
+    use serenity::model::id::RoleId;
+    use crate::model::optional_role;
+    use super::{is_eligible, parse_role_list};
+
+    fn optional_role(required_role_ids: Option<&str>, excluded_role_ids: Option<&str>) -> optional_role::Model {
+        optional_role::Model {
+            role_id: 100,
+            server_id: 1,
+            emoji: None,
+            description: None,
+            group_id: None,
+            required_role_ids: required_role_ids.map(str::to_string),
+            excluded_role_ids: excluded_role_ids.map(str::to_string),
+            duration_minutes: None
+        }
+    }
+
+    #[test]
+    fn parses_ids_and_mentions() {
+        assert_eq!(parse_role_list("<@&1>, 2 3"), vec![RoleId(1), RoleId(2), RoleId(3)]);
+        assert_eq!(parse_role_list("4,,<@&5>"), vec![RoleId(4), RoleId(5)]);
+        assert!(parse_role_list("everyone").is_empty());
+    }
+
+    #[test]
+    fn roles_without_requirements_are_open_to_everyone() {
+        assert!(is_eligible(&optional_role(None, None), &[]));
+        assert!(is_eligible(&optional_role(None, None), &[RoleId(1)]));
+    }
+
+    #[test]
+    fn any_required_role_is_enough() {
+        let role = optional_role(Some("1,2"), None);
+        assert!(!is_eligible(&role, &[]));
+        assert!(!is_eligible(&role, &[RoleId(3)]));
+        assert!(is_eligible(&role, &[RoleId(2)]));
+    }
+
+    #[test]
+    fn excluded_roles_block_picking() {
+        let role = optional_role(Some("1"), Some("2"));
+        assert!(is_eligible(&role, &[RoleId(1)]));
+        assert!(!is_eligible(&role, &[RoleId(1), RoleId(2)]));
+        assert!(!is_eligible(&optional_role(None, Some("2")), &[RoleId(2)]));
+    }
+}
@@ -0,0 +1,547 @@
+use chrono::Utc;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{DatabaseConnection, EntityTrait, ActiveModelTrait, QueryFilter, QueryOrder, ColumnTrait, ModelTrait, PaginatorTrait};
+use serenity::builder::{CreateApplicationCommandPermissions, CreateApplicationCommands, CreateComponents};
+use serenity::model::id::{GuildId, UserId};
+use serenity::model::interactions::application_command::{ApplicationCommandInteraction, ApplicationCommandInteractionDataOptionValue, ApplicationCommandOptionType, ApplicationCommandPermissionType};
+use serenity::model::interactions::InteractionResponseType;
+use serenity::model::interactions::message_component::ButtonStyle;
+use serenity::model::prelude::InteractionApplicationCommandCallbackDataFlags;
+use serenity::model::prelude::message_component::MessageComponentInteraction;
+use serenity::prelude::Context;
+use super::punishments;
+use crate::error::RaincoatError;
+use crate::model::rules_answer;
+use crate::model::rules_question;
+use crate::model::server;
+use super::verification;
+use super::verification::VerificationOutcome;
+
+// The rules and questions are shown in a single message, which Discord limits to 2000 characters
+const MAX_RULES_LENGTH: usize = 1200;
+const MAX_PROMPT_LENGTH: usize = 100;
+const MAX_QUESTIONS: u64 = 5;
+
+const CHECKBOX_ANSWER: &str = "Agreed";
+
+pub fn create_command(commands: &mut CreateApplicationCommands) {
+    commands.create_application_command(|command| {
+        command.name("rules")
+            .description("Configure the rules users must accept to verify")
+            .default_permission(false)
+            .create_option(|option| {
+                option.name("set")
+                    .description("Set the rules text")
+                    .kind(ApplicationCommandOptionType::SubCommand)
+                    .create_sub_option(|suboption| {
+                        suboption.name("text")
+                            .description("The rules, with \\n for new lines")
+                            .kind(ApplicationCommandOptionType::String)
+                            .required(false)
+                    })
+                    .create_sub_option(|suboption| {
+                        suboption.name("message")
+                            .description("The ID of a message in this channel to copy the rules from")
+                            .kind(ApplicationCommandOptionType::String)
+                            .required(false)
+                    })
+            })
+            .create_option(|option| {
+                option.name("require")
+                    .description("Choose whether users must accept the rules to verify")
+                    .kind(ApplicationCommandOptionType::SubCommand)
+                    .create_sub_option(|suboption| {
+                        suboption.name("enabled")
+                            .description("Whether users must accept the rules to verify")
+                            .kind(ApplicationCommandOptionType::Boolean)
+                            .required(true)
+                    })
+            })
+            .create_option(|option| {
+                option.name("addquestion")
+                    .description("Add a question users must answer when accepting the rules")
+                    .kind(ApplicationCommandOptionType::SubCommand)
+                    .create_sub_option(|suboption| {
+                        suboption.name("prompt")
+                            .description("The question to ask")
+                            .kind(ApplicationCommandOptionType::String)
+                            .required(true)
+                    })
+                    .create_sub_option(|suboption| {
+                        suboption.name("type")
+                            .description("How the question is answered")
+                            .kind(ApplicationCommandOptionType::String)
+                            .add_string_choice("Checkbox", "checkbox")
+                            .add_string_choice("Free text", "text")
+                            .required(true)
+                    })
+            })
+            .create_option(|option| {
+                option.name("removequestion")
+                    .description("Remove a question, along with its answers")
+                    .kind(ApplicationCommandOptionType::SubCommand)
+                    .create_sub_option(|suboption| {
+                        suboption.name("id")
+                            .description("The ID of the question to remove, as shown by /rules questions")
+                            .kind(ApplicationCommandOptionType::Integer)
+                            .required(true)
+                    })
+            })
+            .create_option(|option| {
+                option.name("questions")
+                    .description("List the rules questions for this server")
+                    .kind(ApplicationCommandOptionType::SubCommand)
+            })
+            .create_option(|option| {
+                option.name("answers")
+                    .description("Show a user's answers to the rules questions")
+                    .kind(ApplicationCommandOptionType::SubCommand)
+                    .create_sub_option(|suboption| {
+                        suboption.name("user")
+                            .description("The user to show answers for")
+                            .kind(ApplicationCommandOptionType::User)
+                            .required(true)
+                    })
+            })
+    });
+
+    commands.create_application_command(|command| {
+        command.name("answer")
+            .description("Answer one of this server's rules questions")
+            .create_option(|option| {
+                option.name("question")
+                    .kind(ApplicationCommandOptionType::Integer)
+                    .description("The number of the question you are answering")
+                    .required(true)
+            })
+            .create_option(|option| {
+                option.name("text")
+                    .kind(ApplicationCommandOptionType::String)
+                    .description("Your answer")
+                    .required(true)
+            })
+    });
+}
+
+pub fn create_permissions(mod_role: u64, updater: &mut CreateApplicationCommandPermissions) -> &mut CreateApplicationCommandPermissions {
+    updater.create_permissions(|permissions| {
+        permissions.kind(ApplicationCommandPermissionType::Role)
+            .id(mod_role)
+            .permission(true)
+    })
+}
+
+/// Builds the message asking a user to accept the rules, showing which questions they still need to answer.
+///
+/// Serenity can't receive modal submissions yet, so checkbox questions are buttons on this message and free text questions are answered with `/answer`.
+pub async fn rules_message(db: &DatabaseConnection, server_model: &server::Model, user_id: UserId, notice: Option<&str>) -> Result<(String, CreateComponents), RaincoatError> {
+    let questions: Vec<rules_question::Model> = rules_question::Entity::find()
+        .filter(rules_question::Column::ServerId.eq(server_model.id))
+        .order_by_asc(rules_question::Column::Id)
+        .all(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+    let answered: Vec<i64> = rules_answer::Entity::find()
+        .filter(rules_answer::Column::ServerId.eq(server_model.id))
+        .filter(rules_answer::Column::UserId.eq(user_id.0 as i64))
+        .all(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?
+        .into_iter()
+        .map(|answer| answer.question_id)
+        .collect();
+
+    let mut content = String::new();
+    if let Some(notice) = notice {
+        content.push_str(notice);
+        content.push('\n');
+    }
+    content.push_str("**Please read and accept the rules of this server.**\n");
+    if let Some(rules_text) = &server_model.rules_text {
+        content.push_str(rules_text);
+        content.push('\n');
+    }
+    if !questions.is_empty() {
+        content.push_str("\n**Questions**\n");
+        for question in &questions {
+            let status = if answered.contains(&question.id) { "\u{2705}" } else { "\u{274C}" };
+            if question.free_text {
+                content.push_str(&format!("{} #{}: {} (answer with `/answer question:{}` in the server)\n", status, question.id, question.prompt, question.id));
+            } else {
+                content.push_str(&format!("{} #{}: {}\n", status, question.id, question.prompt));
+            }
+        }
+    }
+
+    let server_id = server_model.id;
+    let mut components = CreateComponents::default();
+    let checkboxes: Vec<&rules_question::Model> = questions.iter()
+        .filter(|question| !question.free_text)
+        .collect();
+    if !checkboxes.is_empty() {
+        components.create_action_row(|row| {
+            for question in checkboxes {
+                let checked = answered.contains(&question.id);
+                row.create_button(|button| {
+                    button.custom_id(format!("rules:{}:check:{}", server_id, question.id))
+                        .label(format!("{} #{}", if checked { "Agreed to" } else { "Agree to" }, question.id))
+                        .style(if checked { ButtonStyle::Success } else { ButtonStyle::Secondary })
+                });
+            }
+            row
+        });
+    }
+    components.create_action_row(|row| {
+        row.create_button(|button| {
+            button.custom_id(format!("rules:{}:accept", server_id))
+                .label("Accept rules")
+                .style(ButtonStyle::Primary)
+        })
+    });
+
+    Ok((content, components))
+}
+
+pub async fn create_response(db: &DatabaseConnection, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<(), RaincoatError> {
+    let server_id = command.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
+
+    let subcommand = command.data.options.first().ok_or(RaincoatError { cause: "Command target is required.".to_string() })?;
+
+    let content = match subcommand.name.as_str() {
+        "set" => {
+            let mut rules_text_opt: Option<String> = None;
+
+            for option in &subcommand.options {
+                match option.name.as_str() {
+                    "text" => rules_text_opt = Some(punishments::parse_string_option("text", option)?.replace("\\n", "\n")),
+                    "message" => {
+                        let message_id_str = punishments::parse_string_option("message", option)?;
+                        let message_id: u64 = message_id_str.parse()
+                            .map_err(|_err| RaincoatError { cause: format!("Couldn't parse {} as message id", message_id_str) })?;
+                        let message = command.channel_id.message(&ctx.http, message_id).await
+                            .map_err(|err| RaincoatError { cause: format!("Couldn't find message {} in this channel: {}", message_id, err) })?;
+                        rules_text_opt = Some(message.content);
+                    }
+                    unknown => return Err(RaincoatError { cause: format!("Unknown parameter: {}", unknown) })
+                }
+            }
+
+            let rules_text = rules_text_opt.ok_or(RaincoatError { cause: "Requires 'text' or 'message' param".to_string() })?;
+            if rules_text.chars().count() > MAX_RULES_LENGTH {
+                return Err(RaincoatError { cause: format!("The rules can be at most {} characters long", MAX_RULES_LENGTH) });
+            }
+
+            server::ActiveModel {
+                id: Set(server_id.0 as i64),
+                rules_text: Set(Some(rules_text)),
+                ..Default::default()
+            }.update(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+            "Updated the rules.".to_string()
+        }
+        "require" => {
+            let mut enabled_opt: Option<bool> = None;
+
+            for option in &subcommand.options {
+                match option.name.as_str() {
+                    "enabled" => {
+                        if let ApplicationCommandInteractionDataOptionValue::Boolean(enabled) = &option.resolved.as_ref()
+                            .ok_or(RaincoatError { cause: "Couldn't resolve 'enabled' param".to_string() })? {
+                            enabled_opt = Some(*enabled);
+                        } else {
+                            return Err(RaincoatError { cause: "Unexpected type for 'enabled' param".to_string() });
+                        }
+                    }
+                    unknown => return Err(RaincoatError { cause: format!("Unknown parameter: {}", unknown) })
+                }
+            }
+
+            let enabled = enabled_opt.ok_or(RaincoatError { cause: "Requires 'enabled' param".to_string() })?;
+
+            if enabled {
+                let server_model = server::Entity::find_by_id(server_id.0 as i64).one(db).await
+                    .map_err(|err| RaincoatError { cause: format!("{}", err) })?
+                    .ok_or(RaincoatError { cause: "This server has not been set up.".to_string() })?;
+                let question_count = rules_question::Entity::find()
+                    .filter(rules_question::Column::ServerId.eq(server_id.0 as i64))
+                    .count(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+                if server_model.rules_text.is_none() && question_count == 0 {
+                    return Err(RaincoatError { cause: "Set the rules with /rules set or add a question first".to_string() });
+                }
+            }
+
+            server::ActiveModel {
+                id: Set(server_id.0 as i64),
+                rules_required: Set(enabled),
+                ..Default::default()
+            }.update(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+            if enabled {
+                "Users must now accept the rules to verify.".to_string()
+            } else {
+                "Users no longer need to accept the rules to verify.".to_string()
+            }
+        }
+        "addquestion" => {
+            let mut prompt_opt: Option<String> = None;
+            let mut free_text_opt: Option<bool> = None;
+
+            for option in &subcommand.options {
+                match option.name.as_str() {
+                    "prompt" => prompt_opt = Some(punishments::parse_string_option("prompt", option)?),
+                    "type" => {
+                        free_text_opt = match punishments::parse_string_option("type", option)?.as_str() {
+                            "checkbox" => Some(false),
+                            "text" => Some(true),
+                            other => return Err(RaincoatError { cause: format!("Unknown question type: {}", other) })
+                        };
+                    }
+                    unknown => return Err(RaincoatError { cause: format!("Unknown parameter: {}", unknown) })
+                }
+            }
+
+            let prompt = prompt_opt.ok_or(RaincoatError { cause: "Requires 'prompt' param".to_string() })?;
+            let free_text = free_text_opt.ok_or(RaincoatError { cause: "Requires 'type' param".to_string() })?;
+            if prompt.chars().count() > MAX_PROMPT_LENGTH {
+                return Err(RaincoatError { cause: format!("Questions can be at most {} characters long", MAX_PROMPT_LENGTH) });
+            }
+
+            let question_count = rules_question::Entity::find()
+                .filter(rules_question::Column::ServerId.eq(server_id.0 as i64))
+                .count(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+            if question_count as u64 >= MAX_QUESTIONS {
+                return Err(RaincoatError { cause: format!("Servers can have at most {} rules questions", MAX_QUESTIONS) });
+            }
+
+            let question = rules_question::ActiveModel {
+                server_id: Set(server_id.0 as i64),
+                prompt: Set(prompt),
+                free_text: Set(free_text),
+                ..Default::default()
+            }.insert(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+            format!("Added question #{}.", question.id)
+        }
+        "removequestion" => {
+            let mut question_id_opt: Option<i64> = None;
+
+            for option in &subcommand.options {
+                match option.name.as_str() {
+                    "id" => question_id_opt = Some(punishments::parse_integer_option("id", option)?),
+                    unknown => return Err(RaincoatError { cause: format!("Unknown parameter: {}", unknown) })
+                }
+            }
+
+            let question_id = question_id_opt.ok_or(RaincoatError { cause: "Requires 'id' param".to_string() })?;
+
+            let question = rules_question::Entity::find_by_id(question_id)
+                .filter(rules_question::Column::ServerId.eq(server_id.0 as i64))
+                .one(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?
+                .ok_or(RaincoatError { cause: format!("There is no question #{} on this server", question_id) })?;
+            question.delete(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+            format!("Removed question #{}.", question_id)
+        }
+        "questions" => {
+            let questions: Vec<rules_question::Model> = rules_question::Entity::find()
+                .filter(rules_question::Column::ServerId.eq(server_id.0 as i64))
+                .order_by_asc(rules_question::Column::Id)
+                .all(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+            if questions.is_empty() {
+                "There are no rules questions on this server.".to_string()
+            } else {
+                questions.iter()
+                    .map(|question| format!("#{}: {} ({})", question.id, question.prompt, if question.free_text { "free text" } else { "checkbox" }))
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            }
+        }
+        "answers" => {
+            let mut user_id_opt: Option<u64> = None;
+
+            for option in &subcommand.options {
+                match option.name.as_str() {
+                    "user" => {
+                        if let ApplicationCommandInteractionDataOptionValue::User(user, _member) = &option.resolved.as_ref()
+                            .ok_or(RaincoatError { cause: "Couldn't resolve 'user' param".to_string() })? {
+                            user_id_opt = Some(user.id.0);
+                        } else {
+                            return Err(RaincoatError { cause: "Unexpected type for 'user' param".to_string() });
+                        }
+                    }
+                    unknown => return Err(RaincoatError { cause: format!("Unknown parameter: {}", unknown) })
+                }
+            }
+
+            let user_id = user_id_opt.ok_or(RaincoatError { cause: "Requires 'user' param".to_string() })?;
+
+            let answers: Vec<(rules_answer::Model, Option<rules_question::Model>)> = rules_answer::Entity::find()
+                .filter(rules_answer::Column::ServerId.eq(server_id.0 as i64))
+                .filter(rules_answer::Column::UserId.eq(user_id as i64))
+                .order_by_asc(rules_answer::Column::QuestionId)
+                .find_also_related(rules_question::Entity)
+                .all(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+            return command.create_interaction_response(&ctx.http, |response| {
+                response.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message.create_embed(|e| {
+                            e.title("Rules answers");
+                            if answers.is_empty() {
+                                e.description(format!("<@{}> has not answered any rules questions.", user_id));
+                            } else {
+                                e.description(format!("Answers from <@{}>", user_id));
+                                // Answers are deleted along with their question, so every answer has one
+                                for (answer, question) in answers.iter().filter_map(|(answer, question)| question.as_ref().map(|question| (answer, question))) {
+                                    e.field(format!("#{}: {}", answer.question_id, question.prompt),
+                                            format!("{}\nAnswered <t:{}>", answer.answer, answer.answered_at.timestamp()), false);
+                                }
+                            }
+                            e
+                        }).allowed_mentions(|f| f.empty_parse())
+                            .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                    })
+            }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) });
+        }
+        unknown => return Err(RaincoatError { cause: format!("Unknown subcommand: {}", unknown) })
+    };
+
+    command.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                message.content(content)
+            })
+    }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
+}
+
+pub async fn create_answer_response(db: &DatabaseConnection, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<(), RaincoatError> {
+    let server_id = command.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
+
+    let mut question_id_opt: Option<i64> = None;
+    let mut text_opt: Option<String> = None;
+
+    for option in &command.data.options {
+        match option.name.as_str() {
+            "question" => question_id_opt = Some(punishments::parse_integer_option("question", option)?),
+            "text" => text_opt = Some(punishments::parse_string_option("text", option)?),
+            unknown => {
+                return Err(RaincoatError { cause: format!("Unknown param: {}", unknown)})
+            }
+        }
+    }
+
+    let question_id = question_id_opt.ok_or(RaincoatError { cause: "Requires 'question' param".to_string() })?;
+    let text = text_opt.ok_or(RaincoatError { cause: "Requires 'text' param".to_string() })?;
+
+    let question = rules_question::Entity::find_by_id(question_id)
+        .filter(rules_question::Column::ServerId.eq(server_id.0 as i64))
+        .one(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?
+        .ok_or(RaincoatError { cause: format!("There is no question #{} on this server", question_id) })?;
+    if !question.free_text {
+        return Err(RaincoatError { cause: format!("Question #{} is answered with its button on the rules message", question_id) });
+    }
+
+    save_answer(db, server_id, command.user.id, question_id, text).await?;
+
+    command.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                message.content(format!("Saved your answer to question #{}. Press **Accept rules** on the rules message once you have answered every question.", question_id))
+                    .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+            })
+    }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
+}
+
+async fn save_answer(db: &DatabaseConnection, server_id: GuildId, user_id: UserId, question_id: i64, answer: String) -> Result<(), RaincoatError> {
+    let existing = rules_answer::Entity::find()
+        .filter(rules_answer::Column::QuestionId.eq(question_id))
+        .filter(rules_answer::Column::UserId.eq(user_id.0 as i64))
+        .one(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+    match existing {
+        Some(existing) => {
+            let mut updated: rules_answer::ActiveModel = existing.into();
+            updated.answer = Set(answer);
+            updated.answered_at = Set(Utc::now().naive_utc());
+            updated.update(db).await
+        }
+        None => {
+            rules_answer::ActiveModel {
+                question_id: Set(question_id),
+                server_id: Set(server_id.0 as i64),
+                user_id: Set(user_id.0 as i64),
+                answer: Set(answer),
+                answered_at: Set(Utc::now().naive_utc()),
+                ..Default::default()
+            }.insert(db).await
+        }
+    }.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+    Ok(())
+}
+
+pub async fn create_component_response(db: &DatabaseConnection, ctx: &Context, component: &MessageComponentInteraction) -> Result<(), RaincoatError> {
+    // The rules can be accepted in DMs, so the server comes from the button rather than the interaction
+    let mut parts = component.data.custom_id.split(':').skip(1);
+    let server_id: u64 = parts.next()
+        .and_then(|server_id| server_id.parse().ok())
+        .ok_or(RaincoatError { cause: "Malformed rules button".to_string() })?;
+    let action = parts.next()
+        .ok_or(RaincoatError { cause: "Malformed rules button".to_string() })?;
+
+    let server_model = server::Entity::find_by_id(server_id as i64).one(db).await
+        .map_err(|err| RaincoatError { cause: format!("{}", err) })?
+        .ok_or(RaincoatError { cause: "This server has not been set up.".to_string() })?;
+    let user_id = component.user.id;
+
+    let (content, components) = match action {
+        "check" => {
+            let question_id: i64 = parts.next()
+                .and_then(|question_id| question_id.parse().ok())
+                .ok_or(RaincoatError { cause: "Malformed rules button".to_string() })?;
+
+            // Buttons come from DMs, so make sure the question really belongs to the server named by the button
+            let question = rules_question::Entity::find_by_id(question_id)
+                .filter(rules_question::Column::ServerId.eq(server_id as i64))
+                .one(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?
+                .ok_or(RaincoatError { cause: "This question has been removed, please start verifying again.".to_string() })?;
+
+            let existing = rules_answer::Entity::find()
+                .filter(rules_answer::Column::QuestionId.eq(question.id))
+                .filter(rules_answer::Column::UserId.eq(user_id.0 as i64))
+                .one(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+            match existing {
+                Some(existing) => {
+                    existing.delete(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+                }
+                None => save_answer(db, GuildId(server_id), user_id, question.id, CHECKBOX_ANSWER.to_string()).await?
+            }
+
+            rules_message(db, &server_model, user_id, None).await?
+        }
+        "accept" => {
+            let unanswered = rules_question::Entity::find()
+                .filter(rules_question::Column::ServerId.eq(server_id as i64))
+                .find_with_related(rules_answer::Entity)
+                .all(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?
+                .into_iter()
+                .filter(|(_question, answers)| !answers.iter().any(|answer| answer.user_id == user_id.0 as i64))
+                .count();
+
+            if unanswered > 0 {
+                rules_message(db, &server_model, user_id, Some("Please answer every question before accepting the rules.")).await?
+            } else {
+                match verification::complete_verification(db, ctx, &server_model, user_id).await? {
+                    VerificationOutcome::Challenged(content, components) => (content, components),
+                    outcome => (outcome.message().to_string(), CreateComponents::default())
+                }
+            }
+        }
+        unknown => return Err(RaincoatError { cause: format!("Unknown rules action: {}", unknown) })
+    };
+
+    component.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::UpdateMessage)
+            .interaction_response_data(|message| {
+                message.content(content)
+                    .set_components(components)
+            })
+    }).await.map_err(|err| RaincoatError { cause: format!("Failed to respond to component: {}", err) })
+}
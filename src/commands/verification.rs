@@ -1,12 +1,23 @@
-use sea_orm::ActiveValue::Set;
-use sea_orm::{DatabaseConnection, ActiveModelTrait};
-use serenity::builder::{CreateApplicationCommandPermissions, CreateApplicationCommands};
-use serenity::model::interactions::application_command::{ApplicationCommandInteractionDataOptionValue, ApplicationCommandOptionType};
+use chrono::{DateTime, Duration, Utc};
+use sea_orm::ActiveValue::{NotSet, Set};
+use sea_orm::{DatabaseConnection, ActiveModelTrait, EntityTrait};
+use serenity::builder::{CreateApplicationCommandPermissions, CreateApplicationCommands, CreateComponents};
+use serenity::model::channel::{ChannelType, ReactionType};
+use serenity::model::id::{ChannelId, GuildId, RoleId, UserId};
+use serenity::model::interactions::application_command::{ApplicationCommandInteractionDataOption, ApplicationCommandInteractionDataOptionValue, ApplicationCommandOptionType};
 use serenity::model::interactions::InteractionResponseType;
+use serenity::model::interactions::message_component::ButtonStyle;
 use serenity::model::prelude::application_command::{ApplicationCommandInteraction, ApplicationCommandPermissionType};
+use serenity::model::prelude::InteractionApplicationCommandCallbackDataFlags;
+use serenity::model::prelude::message_component::MessageComponentInteraction;
 use serenity::prelude::Context;
 use crate::error::RaincoatError;
 use crate::model::server;
+use super::{approval, captcha, rules};
+
+const DEFAULT_PANEL_TITLE: &str = "Verification";
+const DEFAULT_PANEL_TEXT: &str = "Press the button below to verify and gain access to the rest of the server.";
+const DEFAULT_BUTTON_LABEL: &str = "Verify";
 
 pub fn create_command(commands: &mut CreateApplicationCommands) {
     commands.create_application_command(|command| {
@@ -41,6 +52,158 @@ pub fn create_command(commands: &mut CreateApplicationCommands) {
                             .kind(ApplicationCommandOptionType::Integer)
                             .required(false)
                     })
+                    .create_sub_option(|suboption| {
+                        suboption.name("channel")
+                            .description("The channel the message is in (defaults to this channel)")
+                            .kind(ApplicationCommandOptionType::Channel)
+                            .channel_types(&[ChannelType::Text])
+                            .required(false)
+                    })
+                    .create_sub_option(|suboption| {
+                        suboption.name("revoke")
+                            .description("Whether to take the role away again if the reaction is removed")
+                            .kind(ApplicationCommandOptionType::Boolean)
+                            .required(false)
+                    })
+            })
+            .create_option(|option| {
+                option.name("panel")
+                    .description("Post a verification panel with a button users press to verify")
+                    .kind(ApplicationCommandOptionType::SubCommand)
+                    .create_sub_option(|suboption| {
+                        suboption.name("channel")
+                            .description("The channel to post the panel in")
+                            .kind(ApplicationCommandOptionType::Channel)
+                            .channel_types(&[ChannelType::Text])
+                            .required(true)
+                    })
+                    .create_sub_option(|suboption| {
+                        suboption.name("role")
+                            .description("The role to give users who verify")
+                            .kind(ApplicationCommandOptionType::Role)
+                            .required(true)
+                    })
+                    .create_sub_option(|suboption| {
+                        suboption.name("title")
+                            .description("The title of the panel (defaults to the last panel's title)")
+                            .kind(ApplicationCommandOptionType::String)
+                            .required(false)
+                    })
+                    .create_sub_option(|suboption| {
+                        suboption.name("text")
+                            .description("The body text of the panel (defaults to the last panel's text)")
+                            .kind(ApplicationCommandOptionType::String)
+                            .required(false)
+                    })
+                    .create_sub_option(|suboption| {
+                        suboption.name("label")
+                            .description("The label of the verify button (defaults to the last panel's label)")
+                            .kind(ApplicationCommandOptionType::String)
+                            .required(false)
+                    })
+                    .create_sub_option(|suboption| {
+                        suboption.name("timeout")
+                            .description("The hours to wait before kicking users who do not verify")
+                            .kind(ApplicationCommandOptionType::Integer)
+                            .required(false)
+                    })
+            })
+            .create_option(|option| {
+                option.name("captcha")
+                    .description("Configure the captcha users must solve before they are verified")
+                    .kind(ApplicationCommandOptionType::SubCommand)
+                    .create_sub_option(|suboption| {
+                        suboption.name("enabled")
+                            .description("Whether users must solve a captcha to verify")
+                            .kind(ApplicationCommandOptionType::Boolean)
+                            .required(true)
+                    })
+                    .create_sub_option(|suboption| {
+                        suboption.name("attempts")
+                            .description("Incorrect answers allowed before the user is locked out (defaults to 3, or the current setting)")
+                            .kind(ApplicationCommandOptionType::Integer)
+                            .min_int_value(1)
+                            .required(false)
+                    })
+                    .create_sub_option(|suboption| {
+                        suboption.name("lockout")
+                            .description("Minutes a user is locked out for after too many incorrect answers (defaults to 30, or the current setting)")
+                            .kind(ApplicationCommandOptionType::Integer)
+                            .min_int_value(0)
+                            .required(false)
+                    })
+            })
+            .create_option(|option| {
+                option.name("agegate")
+                    .description("Refuse verification to accounts younger than a minimum age")
+                    .kind(ApplicationCommandOptionType::SubCommand)
+                    .create_sub_option(|suboption| {
+                        suboption.name("minimum")
+                            .description("The minimum account age in hours needed to verify (0 to disable)")
+                            .kind(ApplicationCommandOptionType::Integer)
+                            .min_int_value(0)
+                            .required(true)
+                    })
+                    .create_sub_option(|suboption| {
+                        suboption.name("message")
+                            .description("The message shown to users whose accounts are too new (defaults to the current message)")
+                            .kind(ApplicationCommandOptionType::String)
+                            .required(false)
+                    })
+                    .create_sub_option(|suboption| {
+                        suboption.name("reset_message")
+                            .description("Go back to the default message for accounts that are too new")
+                            .kind(ApplicationCommandOptionType::Boolean)
+                            .required(false)
+                    })
+                    .create_sub_option(|suboption| {
+                        suboption.name("kick_below")
+                            .description("Kick accounts younger than this many hours as soon as they join (0 to disable, defaults to the current setting)")
+                            .kind(ApplicationCommandOptionType::Integer)
+                            .min_int_value(0)
+                            .required(false)
+                    })
+            })
+            .create_option(|option| {
+                option.name("approval")
+                    .description("Have moderators approve users before they are verified")
+                    .kind(ApplicationCommandOptionType::SubCommand)
+                    .create_sub_option(|suboption| {
+                        suboption.name("channel")
+                            .description("The staff channel to post approval requests in (leave out to turn approval off)")
+                            .kind(ApplicationCommandOptionType::Channel)
+                            .channel_types(&[ChannelType::Text])
+                            .required(false)
+                    })
+                    .create_sub_option(|suboption| {
+                        suboption.name("all")
+                            .description("Whether every user needs approval, rather than only accounts below the minimum age")
+                            .kind(ApplicationCommandOptionType::Boolean)
+                            .required(false)
+                    })
+            })
+            .create_option(|option| {
+                option.name("reminders")
+                    .description("DM unverified users before they are kicked for not verifying")
+                    .kind(ApplicationCommandOptionType::SubCommand)
+                    .create_sub_option(|suboption| {
+                        suboption.name("percents")
+                            .description("How far through the timeout to remind users, e.g. \"50, 90\" (leave out to turn reminders off)")
+                            .kind(ApplicationCommandOptionType::String)
+                            .required(false)
+                    })
+                    .create_sub_option(|suboption| {
+                        suboption.name("invite")
+                            .description("An invite link to include when users are kicked, so they can rejoin (defaults to the current link)")
+                            .kind(ApplicationCommandOptionType::String)
+                            .required(false)
+                    })
+                    .create_sub_option(|suboption| {
+                        suboption.name("remove_invite")
+                            .description("Stop sending an invite link to users kicked for not verifying")
+                            .kind(ApplicationCommandOptionType::Boolean)
+                            .required(false)
+                    })
             })
             .create_option(|option| {
                 option.name("disable")
@@ -61,7 +224,7 @@ pub fn create_permissions(mod_role: u64, updater: &mut CreateApplicationCommandP
 pub async fn create_response(db: &DatabaseConnection, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<(), RaincoatError> {
     let server_id = command.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
 
-    let subcommand = command.data.options.get(0).ok_or(RaincoatError { cause: "Command target is required.".to_string() })?;
+    let subcommand = command.data.options.first().ok_or(RaincoatError { cause: "Command target is required.".to_string() })?;
 
     match subcommand.name.as_str() {
         "enable" => {
@@ -69,6 +232,8 @@ pub async fn create_response(db: &DatabaseConnection, ctx: &Context, command: &A
             let mut message_id_opt: Option<u64> = None;
             let mut emoji_opt: Option<String> = None;
             let mut timeout_opt: Option<i64> = None;
+            let mut channel_id = command.channel_id.0;
+            let mut revoke = false;
 
             for option in &subcommand.options {
                 match option.name.as_str() {
@@ -105,6 +270,22 @@ pub async fn create_response(db: &DatabaseConnection, ctx: &Context, command: &A
                             return Err(RaincoatError { cause: "Unexpected type for 'timeout' param".to_string() });
                         }
                     }
+                    "channel" => {
+                        if let ApplicationCommandInteractionDataOptionValue::Channel(channel) = &option.resolved.as_ref()
+                            .ok_or(RaincoatError { cause: "Couldn't resolve 'channel' param".to_string() })? {
+                            channel_id = channel.id.0;
+                        } else {
+                            return Err(RaincoatError { cause: "Unexpected type for 'channel' param".to_string() });
+                        }
+                    }
+                    "revoke" => {
+                        if let ApplicationCommandInteractionDataOptionValue::Boolean(value) = &option.resolved.as_ref()
+                            .ok_or(RaincoatError { cause: "Couldn't resolve 'revoke' param".to_string() })? {
+                            revoke = *value;
+                        } else {
+                            return Err(RaincoatError { cause: "Unexpected type for 'revoke' param".to_string() });
+                        }
+                    }
                     unknown => return Err(RaincoatError { cause: format!("Unknown parameter: {}", unknown) })
                 }
             }
@@ -113,12 +294,294 @@ pub async fn create_response(db: &DatabaseConnection, ctx: &Context, command: &A
             let message_id = message_id_opt.ok_or(RaincoatError { cause: "Requires 'message' param".to_string() })?;
             let emoji = emoji_opt.ok_or( RaincoatError { cause: "Requires 'emoji' param".to_string() })?;
 
+            // Check everything up front, so the mod can fix every problem at once rather than finding them one by one
+            let problems = validate_reaction_target(ctx, server_id, channel_id, message_id, &emoji, RoleId(role_id)).await;
+            if !problems.is_empty() {
+                return Err(RaincoatError { cause: format!("Verification was not configured:\n- {}", problems.join("\n- ")) });
+            }
+
             let new_server = server::ActiveModel {
                 id: Set(server_id.0 as i64),
                 verified_role_id: Set(Some(role_id as i64)),
+                verification_channel_id: Set(Some(channel_id as i64)),
                 verification_message_id: Set(Some(message_id as i64)),
                 verification_emoji: Set(Some(emoji)),
+                verification_revoke_on_unreact: Set(revoke),
+                verification_timeout: Set(timeout_opt),
+                ..Default::default()
+            };
+            new_server.update(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+            command.create_interaction_response(&ctx.http, |response| {
+                response.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message.content("Successfully configured verification.")
+                    })
+            }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
+        }
+        "panel" => {
+            let mut channel_id_opt: Option<u64> = None;
+            let mut role_id_opt: Option<u64> = None;
+            let mut title_opt: Option<String> = None;
+            let mut text_opt: Option<String> = None;
+            let mut label_opt: Option<String> = None;
+            let mut timeout_opt: Option<i64> = None;
+
+            for option in &subcommand.options {
+                match option.name.as_str() {
+                    "channel" => {
+                        if let ApplicationCommandInteractionDataOptionValue::Channel(channel) = &option.resolved.as_ref()
+                            .ok_or(RaincoatError { cause: "Couldn't resolve 'channel' param".to_string() })? {
+                            channel_id_opt = Some(channel.id.0);
+                        } else {
+                            return Err(RaincoatError { cause: "Unexpected type for 'channel' param".to_string() });
+                        }
+                    }
+                    "role" => {
+                        if let ApplicationCommandInteractionDataOptionValue::Role(role) = &option.resolved.as_ref()
+                            .ok_or(RaincoatError { cause: "Couldn't resolve 'role' param".to_string() })? {
+                            role_id_opt = Some(role.id.0);
+                        } else {
+                            return Err(RaincoatError { cause: "Unexpected type for 'role' param".to_string() });
+                        }
+                    }
+                    "title" => title_opt = Some(parse_text_option("title", option, 256)?),
+                    "text" => text_opt = Some(parse_text_option("text", option, 4096)?),
+                    "label" => label_opt = Some(parse_text_option("label", option, 80)?),
+                    "timeout" => {
+                        if let ApplicationCommandInteractionDataOptionValue::Integer(timeout) = &option.resolved.as_ref()
+                            .ok_or(RaincoatError { cause: "Couldn't resolve 'timeout' param".to_string() })? {
+                            timeout_opt = Some(*timeout);
+                        } else {
+                            return Err(RaincoatError { cause: "Unexpected type for 'timeout' param".to_string() });
+                        }
+                    }
+                    unknown => return Err(RaincoatError { cause: format!("Unknown parameter: {}", unknown) })
+                }
+            }
+
+            let channel_id = channel_id_opt.ok_or(RaincoatError { cause: "Requires 'channel' param".to_string() })?;
+            let role_id = role_id_opt.ok_or(RaincoatError { cause: "Requires 'role' param".to_string() })?;
+            if let Some(problem) = check_role_manageable(ctx, server_id, RoleId(role_id)).await {
+                return Err(RaincoatError { cause: problem });
+            }
+
+            // Re-posting the panel keeps whatever wording it was last given
+            let server_model = server::Entity::find_by_id(server_id.0 as i64).one(db).await
+                .map_err(|err| RaincoatError { cause: format!("{}", err) })?
+                .ok_or(RaincoatError { cause: "This server has not been set up.".to_string() })?;
+            let title_opt = title_opt.or(server_model.verification_panel_title);
+            let text_opt = text_opt.or(server_model.verification_panel_text);
+            let label_opt = label_opt.or(server_model.verification_button_label);
+
+            let panel_message = ChannelId(channel_id).send_message(&ctx.http, |m| {
+                m.embed(|e| {
+                    e.title(title_opt.as_deref().unwrap_or(DEFAULT_PANEL_TITLE))
+                        .description(text_opt.as_deref().unwrap_or(DEFAULT_PANEL_TEXT))
+                })
+                    .components(|c| {
+                        c.create_action_row(|row| {
+                            row.create_button(|button| {
+                                button.custom_id("verify")
+                                    .label(label_opt.as_deref().unwrap_or(DEFAULT_BUTTON_LABEL))
+                                    .style(ButtonStyle::Success)
+                            })
+                        })
+                    })
+            }).await.map_err(|err| RaincoatError { cause: format!("Failed to post verification panel: {}", err) })?;
+
+            // The panel replaces any reaction verification that was set up before
+            let new_server = server::ActiveModel {
+                id: Set(server_id.0 as i64),
+                verified_role_id: Set(Some(role_id as i64)),
+                verification_channel_id: Set(Some(channel_id as i64)),
+                verification_message_id: Set(Some(panel_message.id.0 as i64)),
+                verification_emoji: Set(None),
+                verification_revoke_on_unreact: Set(false),
                 verification_timeout: Set(timeout_opt),
+                verification_panel_title: Set(title_opt),
+                verification_panel_text: Set(text_opt),
+                verification_button_label: Set(label_opt),
+                ..Default::default()
+            };
+            new_server.update(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+            command.create_interaction_response(&ctx.http, |response| {
+                response.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message.content(format!("Posted verification panel in <#{}>.", channel_id))
+                    })
+            }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
+        }
+        "captcha" => {
+            let mut enabled_opt: Option<bool> = None;
+            let mut attempts_opt: Option<i64> = None;
+            let mut lockout_minutes_opt: Option<i64> = None;
+
+            for option in &subcommand.options {
+                match option.name.as_str() {
+                    "enabled" => {
+                        if let ApplicationCommandInteractionDataOptionValue::Boolean(enabled) = &option.resolved.as_ref()
+                            .ok_or(RaincoatError { cause: "Couldn't resolve 'enabled' param".to_string() })? {
+                            enabled_opt = Some(*enabled);
+                        } else {
+                            return Err(RaincoatError { cause: "Unexpected type for 'enabled' param".to_string() });
+                        }
+                    }
+                    "attempts" => {
+                        if let ApplicationCommandInteractionDataOptionValue::Integer(value) = &option.resolved.as_ref()
+                            .ok_or(RaincoatError { cause: "Couldn't resolve 'attempts' param".to_string() })? {
+                            attempts_opt = Some(*value);
+                        } else {
+                            return Err(RaincoatError { cause: "Unexpected type for 'attempts' param".to_string() });
+                        }
+                    }
+                    "lockout" => {
+                        if let ApplicationCommandInteractionDataOptionValue::Integer(value) = &option.resolved.as_ref()
+                            .ok_or(RaincoatError { cause: "Couldn't resolve 'lockout' param".to_string() })? {
+                            lockout_minutes_opt = Some(*value);
+                        } else {
+                            return Err(RaincoatError { cause: "Unexpected type for 'lockout' param".to_string() });
+                        }
+                    }
+                    unknown => return Err(RaincoatError { cause: format!("Unknown parameter: {}", unknown) })
+                }
+            }
+
+            let enabled = enabled_opt.ok_or(RaincoatError { cause: "Requires 'enabled' param".to_string() })?;
+
+            // Settings that weren't given keep their current values, so toggling the captcha doesn't reset them
+            let new_server = server::ActiveModel {
+                id: Set(server_id.0 as i64),
+                captcha_enabled: Set(enabled),
+                captcha_attempts: attempts_opt.map_or(NotSet, Set),
+                captcha_lockout_minutes: lockout_minutes_opt.map_or(NotSet, Set),
+                ..Default::default()
+            };
+            let server_model = new_server.update(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+            command.create_interaction_response(&ctx.http, |response| {
+                response.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        if enabled {
+                            message.content(format!("Users must now solve a captcha to verify, with {} attempt(s) before a {} minute lockout.", server_model.captcha_attempts, server_model.captcha_lockout_minutes))
+                        } else {
+                            message.content("Users no longer need to solve a captcha to verify.")
+                        }
+                    })
+            }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
+        }
+        "agegate" => {
+            let mut minimum_opt: Option<i64> = None;
+            let mut message_opt: Option<String> = None;
+            let mut reset_message = false;
+            let mut kick_below_opt: Option<i64> = None;
+
+            for option in &subcommand.options {
+                match option.name.as_str() {
+                    "minimum" => {
+                        if let ApplicationCommandInteractionDataOptionValue::Integer(value) = &option.resolved.as_ref()
+                            .ok_or(RaincoatError { cause: "Couldn't resolve 'minimum' param".to_string() })? {
+                            minimum_opt = Some(*value);
+                        } else {
+                            return Err(RaincoatError { cause: "Unexpected type for 'minimum' param".to_string() });
+                        }
+                    }
+                    "message" => message_opt = Some(parse_text_option("message", option, 2000)?),
+                    "reset_message" => {
+                        if let ApplicationCommandInteractionDataOptionValue::Boolean(value) = &option.resolved.as_ref()
+                            .ok_or(RaincoatError { cause: "Couldn't resolve 'reset_message' param".to_string() })? {
+                            reset_message = *value;
+                        } else {
+                            return Err(RaincoatError { cause: "Unexpected type for 'reset_message' param".to_string() });
+                        }
+                    }
+                    "kick_below" => {
+                        if let ApplicationCommandInteractionDataOptionValue::Integer(value) = &option.resolved.as_ref()
+                            .ok_or(RaincoatError { cause: "Couldn't resolve 'kick_below' param".to_string() })? {
+                            kick_below_opt = Some(*value);
+                        } else {
+                            return Err(RaincoatError { cause: "Unexpected type for 'kick_below' param".to_string() });
+                        }
+                    }
+                    unknown => return Err(RaincoatError { cause: format!("Unknown parameter: {}", unknown) })
+                }
+            }
+
+            let minimum = minimum_opt.ok_or(RaincoatError { cause: "Requires 'minimum' param".to_string() })?;
+            let minimum_opt = if minimum == 0 { None } else { Some(minimum) };
+            if message_opt.is_some() && reset_message {
+                return Err(RaincoatError { cause: "Only one of 'message' and 'reset_message' may be given".to_string() });
+            }
+
+            let server_model = server::Entity::find_by_id(server_id.0 as i64).one(db).await
+                .map_err(|err| RaincoatError { cause: format!("{}", err) })?
+                .ok_or(RaincoatError { cause: "This server has not been set up.".to_string() })?;
+            // Settings that weren't given keep their current values, so changing the minimum doesn't reset them
+            let kick_below = match kick_below_opt {
+                Some(0) => None,
+                Some(kick_below) => Some(kick_below),
+                None => server_model.kick_account_age_hours
+            };
+            if let (Some(minimum), Some(kick_below)) = (minimum_opt, kick_below) {
+                if kick_below > minimum {
+                    return Err(RaincoatError { cause: "'kick_below' can't be more than 'minimum'".to_string() });
+                }
+            }
+
+            let new_server = server::ActiveModel {
+                id: Set(server_id.0 as i64),
+                min_account_age_hours: Set(minimum_opt),
+                account_age_message: if reset_message { Set(None) } else { message_opt.map_or(NotSet, |message| Set(Some(message))) },
+                kick_account_age_hours: Set(kick_below),
+                ..Default::default()
+            };
+            new_server.update(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+            command.create_interaction_response(&ctx.http, |response| {
+                response.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        let mut content = match minimum_opt {
+                            Some(minimum) => format!("Accounts younger than {} hours can no longer verify.", minimum),
+                            None => "Accounts of any age can now verify.".to_string()
+                        };
+                        if let Some(kick_below) = kick_below {
+                            content.push_str(&format!("\nAccounts younger than {} hours will be kicked when they join.", kick_below));
+                        }
+                        message.content(content)
+                    })
+            }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
+        }
+        "approval" => {
+            let mut channel_id_opt: Option<u64> = None;
+            let mut all = false;
+
+            for option in &subcommand.options {
+                match option.name.as_str() {
+                    "channel" => {
+                        if let ApplicationCommandInteractionDataOptionValue::Channel(channel) = &option.resolved.as_ref()
+                            .ok_or(RaincoatError { cause: "Couldn't resolve 'channel' param".to_string() })? {
+                            channel_id_opt = Some(channel.id.0);
+                        } else {
+                            return Err(RaincoatError { cause: "Unexpected type for 'channel' param".to_string() });
+                        }
+                    }
+                    "all" => {
+                        if let ApplicationCommandInteractionDataOptionValue::Boolean(value) = &option.resolved.as_ref()
+                            .ok_or(RaincoatError { cause: "Couldn't resolve 'all' param".to_string() })? {
+                            all = *value;
+                        } else {
+                            return Err(RaincoatError { cause: "Unexpected type for 'all' param".to_string() });
+                        }
+                    }
+                    unknown => return Err(RaincoatError { cause: format!("Unknown parameter: {}", unknown) })
+                }
+            }
+
+            let new_server = server::ActiveModel {
+                id: Set(server_id.0 as i64),
+                approval_channel_id: Set(channel_id_opt.map(|channel_id| channel_id as i64)),
+                approval_required: Set(channel_id_opt.is_some() && all),
                 ..Default::default()
             };
             new_server.update(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
@@ -126,7 +589,79 @@ pub async fn create_response(db: &DatabaseConnection, ctx: &Context, command: &A
             command.create_interaction_response(&ctx.http, |response| {
                 response.kind(InteractionResponseType::ChannelMessageWithSource)
                     .interaction_response_data(|message| {
-                        message.content(format!("Successfully configured verification."))
+                        match channel_id_opt {
+                            Some(channel_id) if all => message.content(format!("Every user must now be approved in <#{}> before they are verified.", channel_id)),
+                            Some(channel_id) => message.content(format!("Accounts below the minimum age will now be sent to <#{}> for approval.", channel_id)),
+                            None => message.content("Users no longer need a moderator's approval to verify.")
+                        }
+                    })
+            }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
+        }
+        "reminders" => {
+            let mut percents_opt: Option<String> = None;
+            let mut invite_opt: Option<String> = None;
+            let mut remove_invite = false;
+
+            for option in &subcommand.options {
+                match option.name.as_str() {
+                    "percents" => percents_opt = Some(parse_text_option("percents", option, 100)?),
+                    "invite" => invite_opt = Some(parse_text_option("invite", option, 200)?),
+                    "remove_invite" => {
+                        if let ApplicationCommandInteractionDataOptionValue::Boolean(value) = &option.resolved.as_ref()
+                            .ok_or(RaincoatError { cause: "Couldn't resolve 'remove_invite' param".to_string() })? {
+                            remove_invite = *value;
+                        } else {
+                            return Err(RaincoatError { cause: "Unexpected type for 'remove_invite' param".to_string() });
+                        }
+                    }
+                    unknown => return Err(RaincoatError { cause: format!("Unknown parameter: {}", unknown) })
+                }
+            }
+
+            let mut percents = match &percents_opt {
+                Some(percents_str) => {
+                    let percents = parse_reminder_percents(percents_str);
+                    if percents.is_empty() || percents.len() != percents_str.split(',').count() {
+                        return Err(RaincoatError { cause: format!("Couldn't parse {} as a list of percentages between 1 and 99", percents_str) });
+                    }
+                    percents
+                }
+                None => Vec::new()
+            };
+            percents.sort_unstable();
+            percents.dedup();
+
+            if invite_opt.is_some() && remove_invite {
+                return Err(RaincoatError { cause: "Only one of 'invite' and 'remove_invite' may be given".to_string() });
+            }
+
+            let percents_str = percents.iter()
+                .map(|percent| percent.to_string())
+                .collect::<Vec<String>>()
+                .join(",");
+
+            let new_server = server::ActiveModel {
+                id: Set(server_id.0 as i64),
+                verification_reminder_percents: Set(if percents.is_empty() { None } else { Some(percents_str) }),
+                // The invite link is kept unless it is replaced or removed, so changing the reminders doesn't lose it
+                verification_invite_url: if remove_invite { Set(None) } else { invite_opt.map_or(NotSet, |invite| Set(Some(invite))) },
+                ..Default::default()
+            };
+            let server_model = new_server.update(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+            command.create_interaction_response(&ctx.http, |response| {
+                response.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        let mut content = if percents.is_empty() {
+                            "Unverified users will no longer be reminded before they are kicked.".to_string()
+                        } else {
+                            format!("Unverified users will be reminded {} of the way through the verification timeout.",
+                                    percents.iter().map(|percent| format!("{}%", percent)).collect::<Vec<String>>().join(", "))
+                        };
+                        if let Some(invite) = &server_model.verification_invite_url {
+                            content.push_str(&format!("\nUsers kicked for not verifying will be sent {} to rejoin.", invite));
+                        }
+                        message.content(content)
                     })
             }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
         }
@@ -134,6 +669,7 @@ pub async fn create_response(db: &DatabaseConnection, ctx: &Context, command: &A
             let new_server = server::ActiveModel {
                 id: Set(server_id.0 as i64),
                 verified_role_id: Set(None),
+                verification_channel_id: Set(None),
                 verification_message_id: Set(None),
                 verification_emoji: Set(None),
                 verification_timeout: Set(None),
@@ -144,10 +680,235 @@ pub async fn create_response(db: &DatabaseConnection, ctx: &Context, command: &A
             command.create_interaction_response(&ctx.http, |response| {
                 response.kind(InteractionResponseType::ChannelMessageWithSource)
                     .interaction_response_data(|message|  {
-                        message.content(format!("Successfully disabled verification."))
+                        message.content("Successfully disabled verification.")
                     })
             }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
         }
-        unknown => return Err(RaincoatError { cause: format!("Unknown subcommand: {}", unknown) })
+        unknown => Err(RaincoatError { cause: format!("Unknown subcommand: {}", unknown) })
+    }
+}
+
+/// Checks that we are able to give out a role, describing the problem if not.
+pub async fn check_role_manageable(ctx: &Context, server_id: GuildId, role_id: RoleId) -> Option<String> {
+    let server = match ctx.cache.guild(server_id).await {
+        Some(server) => server,
+        None => return Some("Unable to fetch information about this server".to_string())
+    };
+    let role = match server.roles.get(&role_id) {
+        Some(role) => role,
+        None => return Some(format!("<@&{}> does not exist", role_id.0))
+    };
+    if role.managed {
+        return Some(format!("<@&{}> is managed by an integration, so it can't be given out", role_id.0));
+    }
+
+    let bot_id = ctx.cache.current_user_id().await;
+    match server.member_permissions(ctx, bot_id).await {
+        Ok(permissions) if !permissions.manage_roles() => return Some("I need the Manage Roles permission to give out roles".to_string()),
+        Err(err) => return Some(format!("Unable to check my permissions: {}", err)),
+        Ok(_) => {}
+    }
+
+    let bot_position = match server.members.get(&bot_id) {
+        Some(bot_member) => bot_member.highest_role_info(&ctx.cache).await
+            .map(|(_, position)| position)
+            .unwrap_or(0),
+        None => return Some("Unable to fetch information about my roles".to_string())
+    };
+    if role.position >= bot_position {
+        return Some(format!("<@&{}> is not below my highest role, so I can't give it out", role_id.0));
+    }
+
+    None
+}
+
+/// Checks that a role can be handed out for reacting to a message with an emoji, returning every problem found.
+///
+/// The bot reacts to the message itself, which also confirms the emoji is one it is able to use.
+pub async fn validate_reaction_target(ctx: &Context, server_id: GuildId, channel_id: u64, message_id: u64, emoji: &str, role_id: RoleId) -> Vec<String> {
+    let mut problems = Vec::new();
+    if let Some(problem) = check_role_manageable(ctx, server_id, role_id).await {
+        problems.push(problem);
+    }
+    let reaction_type: Option<ReactionType> = match emoji.to_string().try_into() {
+        Ok(reaction_type) => Some(reaction_type),
+        Err(_) => {
+            problems.push(format!("{} is not a valid emoji", emoji));
+            None
+        }
+    };
+    match ChannelId(channel_id).message(&ctx.http, message_id).await {
+        Ok(message) => {
+            if let Some(reaction_type) = reaction_type {
+                if let Err(err) = message.react(&ctx.http, reaction_type).await {
+                    problems.push(format!("Unable to react to the message with {}: {}", emoji, err));
+                }
+            }
+        }
+        Err(err) => problems.push(format!("Couldn't find message {} in <#{}>: {}", message_id, channel_id, err))
+    }
+
+    problems
+}
+
+/// Parses a comma separated list of percentages, such as "50, 90".
+pub fn parse_reminder_percents(value: &str) -> Vec<i64> {
+    value.split(',')
+        .filter_map(|percent| percent.trim().parse().ok())
+        .filter(|percent| (1..100).contains(percent))
+        .collect()
+}
+
+fn parse_text_option(name: &str, option: &ApplicationCommandInteractionDataOption, max_length: usize) -> Result<String, RaincoatError> {
+    if let ApplicationCommandInteractionDataOptionValue::String(value) = &option.resolved.as_ref()
+        .ok_or(RaincoatError { cause: format!("Couldn't resolve '{}' param", name) })? {
+        if value.chars().count() > max_length {
+            return Err(RaincoatError { cause: format!("'{}' can be at most {} characters long", name, max_length) });
+        }
+        Ok(value.clone())
+    } else {
+        Err(RaincoatError { cause: format!("Unexpected type for '{}' param", name) })
+    }
+}
+
+/// What happened when a user asked to be verified.
+pub enum VerificationOutcome {
+    /// The user was given the verified role.
+    Verified,
+    /// The user already has the verified role, so nothing was done.
+    AlreadyVerified,
+    /// The user has to answer a challenge first, which should be shown to them along with its buttons.
+    Challenged(String, CreateComponents),
+    /// The user is waiting on a moderator to approve them, as explained by the message.
+    Queued(String),
+    /// The user can't verify, for the reason given.
+    Refused(String)
+}
+
+impl VerificationOutcome {
+    /// The message to show a user once their verification is complete or waiting on a moderator.
+    pub fn message(&self) -> &str {
+        match self {
+            VerificationOutcome::Verified => "You are now verified.",
+            VerificationOutcome::AlreadyVerified => "You are already verified.",
+            VerificationOutcome::Challenged(content, _) => content,
+            VerificationOutcome::Queued(message) => message,
+            VerificationOutcome::Refused(reason) => reason
+        }
+    }
+}
+
+/// Returns when the user's account will be old enough to verify, if it isn't already.
+fn old_enough_at(server_model: &server::Model, user_id: UserId) -> Option<DateTime<Utc>> {
+    server_model.min_account_age_hours
+        .map(|min_account_age_hours| user_id.created_at() + Duration::hours(min_account_age_hours))
+        .filter(|old_enough_at| *old_enough_at > Utc::now())
+}
+
+/// Verifies a user, or hands them a challenge to complete first if the server requires one.
+pub async fn begin_verification(db: &DatabaseConnection, ctx: &Context, server_model: &server::Model, user_id: UserId) -> Result<VerificationOutcome, RaincoatError> {
+    let server_id = GuildId(server_model.id as u64);
+    let verified_role_id = server_model.verified_role_id
+        .ok_or(RaincoatError { cause: "Verification is not enabled on this server.".to_string() })?;
+
+    // Verified users would otherwise be challenged or queued for approval all over again
+    let member = match ctx.cache.member(server_id, user_id).await {
+        Some(member) => member,
+        None => server_id.member(&ctx.http, user_id).await
+            .map_err(|err| RaincoatError { cause: format!("Unable to fetch information about user {}: {}", user_id.0, err) })?
+    };
+    if member.roles.contains(&RoleId(verified_role_id as u64)) {
+        return Ok(VerificationOutcome::AlreadyVerified);
+    }
+
+    // Accounts that are too new are left to the moderators when there is an approval queue
+    if let Some(old_enough_at) = old_enough_at(server_model, user_id) {
+        if server_model.approval_channel_id.is_none() {
+            let reason = server_model.account_age_message.clone()
+                .unwrap_or_else(|| "Your account is too new to verify on this server.".to_string());
+            return Ok(VerificationOutcome::Refused(format!("{}\nYou will be able to verify <t:{}:R>.", reason, old_enough_at.timestamp())));
+        }
+    }
+
+    if server_model.captcha_enabled {
+        let (content, components) = captcha::start_challenge(db, GuildId(server_model.id as u64), user_id).await?;
+        return Ok(VerificationOutcome::Challenged(content, components));
+    }
+
+    finish_verification(db, ctx, server_model, user_id).await
+}
+
+/// Continues verifying a user who has passed the captcha, asking them to accept the rules if the server requires it.
+pub async fn finish_verification(db: &DatabaseConnection, ctx: &Context, server_model: &server::Model, user_id: UserId) -> Result<VerificationOutcome, RaincoatError> {
+    if server_model.rules_required {
+        let (content, components) = rules::rules_message(db, server_model, user_id, None).await?;
+        return Ok(VerificationOutcome::Challenged(content, components));
+    }
+
+    complete_verification(db, ctx, server_model, user_id).await
+}
+
+/// Verifies a user who has passed every challenge, unless a moderator has to approve them first.
+pub async fn complete_verification(db: &DatabaseConnection, ctx: &Context, server_model: &server::Model, user_id: UserId) -> Result<VerificationOutcome, RaincoatError> {
+    let server_id = GuildId(server_model.id as u64);
+    let verified_role_id = server_model.verified_role_id
+        .ok_or(RaincoatError { cause: "Verification is not enabled on this server.".to_string() })?;
+
+    if server_model.approval_channel_id.is_some() && (server_model.approval_required || old_enough_at(server_model, user_id).is_some()) {
+        let message = approval::queue_for_approval(db, ctx, server_model, user_id).await?;
+        return Ok(VerificationOutcome::Queued(message));
+    }
+
+    grant_verified_role(ctx, server_id, user_id, RoleId(verified_role_id as u64)).await?;
+    Ok(VerificationOutcome::Verified)
+}
+
+/// Gives a user the server's verified role.
+pub async fn grant_verified_role(ctx: &Context, server_id: GuildId, user_id: UserId, verified_role_id: RoleId) -> Result<(), RaincoatError> {
+    ctx.http.add_member_role(server_id.0, user_id.0, verified_role_id.0).await
+        .map_err(|err| RaincoatError { cause: format!("Failed to set verified role in server {}: {}", server_id.0, err) })
+}
+
+pub async fn create_component_response(db: &DatabaseConnection, ctx: &Context, component: &MessageComponentInteraction) -> Result<(), RaincoatError> {
+    let server_id = component.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
+
+    let server_model = server::Entity::find_by_id(server_id.0 as i64).one(db).await
+        .map_err(|err| RaincoatError { cause: format!("{}", err) })?
+        .ok_or(RaincoatError { cause: "This server has not been set up.".to_string() })?;
+
+    // Old panels stop working once verification is disabled or a new panel is posted
+    match (server_model.verified_role_id, server_model.verification_message_id) {
+        (Some(_), Some(verification_message_id)) if verification_message_id as u64 == component.message.id.0 => {}
+        _ => return Err(RaincoatError { cause: "This verification panel is no longer active.".to_string() })
+    };
+
+    let outcome = begin_verification(db, ctx, &server_model, component.user.id).await?;
+
+    component.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                match outcome {
+                    VerificationOutcome::Challenged(content, components) => message.content(content).set_components(components),
+                    outcome => message.content(outcome.message())
+                }.flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+            })
+    }).await.map_err(|err| RaincoatError { cause: format!("Failed to respond to component: {}", err) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_reminder_percents;
+
+    #[test]
+    fn parses_percent_lists() {
+        assert_eq!(parse_reminder_percents("50, 90"), vec![50, 90]);
+        assert_eq!(parse_reminder_percents("25,75"), vec![25, 75]);
+    }
+
+    #[test]
+    fn drops_percents_that_are_out_of_range_or_invalid() {
+        assert_eq!(parse_reminder_percents("0, 50, 100"), vec![50]);
+        assert_eq!(parse_reminder_percents("half, 10%"), Vec::<i64>::new());
+        assert_eq!(parse_reminder_percents(""), Vec::<i64>::new());
     }
 }
@@ -0,0 +1,513 @@
+use chrono::{Duration, Utc};
+use sea_orm::ActiveValue::Set;
+use sea_orm::{DatabaseConnection, EntityTrait, ActiveModelTrait, QueryFilter, QueryOrder, ColumnTrait, ModelTrait};
+use serenity::builder::{CreateApplicationCommandPermissions, CreateApplicationCommands};
+use serenity::model::id::GuildId;
+use serenity::model::interactions::application_command::{ApplicationCommandInteraction, ApplicationCommandInteractionDataOptionValue, ApplicationCommandOptionType, ApplicationCommandPermissionType};
+use serenity::model::interactions::InteractionResponseType;
+use serenity::prelude::Context;
+use crate::commands::punishments;
+use crate::error::RaincoatError;
+use crate::mod_log;
+use crate::mod_log::ModLogEntry;
+use crate::model::punishment;
+use crate::model::punishment::PunishmentType;
+use crate::model::warning;
+use crate::model::warning_escalation;
+
+pub fn create_command(commands: &mut CreateApplicationCommands) {
+    commands.create_application_command(|command| {
+        command.name("warn")
+            .description("Warn a user")
+            .default_permission(false)
+            .create_option(|option| {
+                option.name("user")
+                    .kind(ApplicationCommandOptionType::User)
+                    .description("The user to warn")
+                    .required(true)
+            })
+            .create_option(|option| {
+                option.name("reason")
+                    .kind(ApplicationCommandOptionType::String)
+                    .description("The reason for the warning")
+                    .required(true)
+            })
+    });
+
+    commands.create_application_command(|command| {
+        command.name("warnings")
+            .description("List the warnings a user has received")
+            .default_permission(false)
+            .create_option(|option| {
+                option.name("user")
+                    .kind(ApplicationCommandOptionType::User)
+                    .description("The user to list warnings for")
+                    .required(true)
+            })
+    });
+
+    commands.create_application_command(|command| {
+        command.name("clearwarn")
+            .description("Remove a warning")
+            .default_permission(false)
+            .create_option(|option| {
+                option.name("id")
+                    .kind(ApplicationCommandOptionType::Integer)
+                    .description("The ID of the warning to remove, as shown by /warnings")
+                    .required(true)
+            })
+    });
+
+    commands.create_application_command(|command| {
+        command.name("addescalation")
+            .description("Automatically punish users who collect too many warnings")
+            .default_permission(false)
+            .create_option(|option| {
+                option.name("warnings")
+                    .kind(ApplicationCommandOptionType::Integer)
+                    .description("The number of warnings that triggers the punishment")
+                    .min_int_value(1)
+                    .required(true)
+            })
+            .create_option(|option| {
+                option.name("period")
+                    .kind(ApplicationCommandOptionType::Integer)
+                    .description("The number of days the warnings must be received within")
+                    .min_int_value(1)
+                    .required(true)
+            })
+            .create_option(|option| {
+                option.name("punishment")
+                    .kind(ApplicationCommandOptionType::String)
+                    .description("The punishment to issue")
+                    .add_string_choice("Dunce", "dunce")
+                    .add_string_choice("Ban", "ban")
+                    .add_string_choice("Timeout", "timeout")
+                    .required(true)
+            });
+        punishments::duration_add_options(command)
+    });
+
+    commands.create_application_command(|command| {
+        command.name("removeescalation")
+            .description("Remove an automatic warning punishment")
+            .default_permission(false)
+            .create_option(|option| {
+                option.name("id")
+                    .kind(ApplicationCommandOptionType::Integer)
+                    .description("The ID of the escalation to remove, as shown by /escalations")
+                    .required(true)
+            })
+    });
+
+    commands.create_application_command(|command| {
+        command.name("escalations")
+            .description("List the automatic warning punishments for this server")
+            .default_permission(false)
+    });
+}
+
+pub fn create_permissions(mod_role: u64, updater: &mut CreateApplicationCommandPermissions) -> &mut CreateApplicationCommandPermissions {
+    updater.create_permissions(|permissions| {
+        permissions.kind(ApplicationCommandPermissionType::Role)
+            .id(mod_role)
+            .permission(true)
+    })
+}
+
+fn escalation_description(escalation: &warning_escalation::Model) -> String {
+    let punishment = match escalation.punishment_type {
+        PunishmentType::Dunce => "dunce",
+        PunishmentType::Ban => "ban",
+        PunishmentType::Kick => "kick",
+        PunishmentType::Timeout => "timeout",
+        PunishmentType::Softban => "softban"
+    };
+    let duration = match escalation.duration_minutes {
+        Some(minutes) => format!("for {}", mod_log::format_duration(Duration::minutes(minutes))),
+        None => "indefinitely".to_string()
+    };
+
+    format!("{} warnings within {} days: {} {}", escalation.warning_count, escalation.period_days, punishment, duration)
+}
+
+/// Whether a user's warning count within an escalation's period has just reached its threshold.
+///
+/// Only the exact count matches, and not when a threshold at least as high was already escalated within the period,
+/// so neither later warnings nor old warnings ageing out repeat the same escalation.
+fn reaches_threshold(escalation: &warning_escalation::Model, warning_count: usize, last_escalated_count: Option<i64>) -> bool {
+    warning_count as i64 == escalation.warning_count
+        && last_escalated_count.is_none_or(|last_escalated_count| last_escalated_count < escalation.warning_count)
+}
+
+/// Issues the most severe escalation whose threshold the user has just reached, if any.
+async fn escalate(db: &DatabaseConnection, ctx: &Context, server_id: GuildId, user_id: u64, warning_model: warning::Model) -> Result<Option<punishment::Model>, RaincoatError> {
+    let escalations: Vec<warning_escalation::Model> = warning_escalation::Entity::find()
+        .filter(warning_escalation::Column::ServerId.eq(server_id.0 as i64))
+        .order_by_desc(warning_escalation::Column::WarningCount)
+        .all(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+    for escalation in escalations {
+        let warnings: Vec<warning::Model> = warning::Entity::find()
+            .filter(warning::Column::ServerId.eq(server_id.0 as i64))
+            .filter(warning::Column::UserId.eq(user_id as i64))
+            .filter(warning::Column::CreatedAt.gt((Utc::now() - Duration::days(escalation.period_days)).naive_utc()))
+            .all(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+        let last_escalated_count = warnings.iter()
+            .filter_map(|warning| warning.escalated_count)
+            .max();
+
+        if !reaches_threshold(&escalation, warnings.len(), last_escalated_count) {
+            continue;
+        }
+
+        let expires = escalation.duration_minutes
+            .map(|minutes| punishments::expires_after(Duration::minutes(minutes)))
+            .transpose()?;
+        let reason = Some(format!("Reached {} warnings within {} days", escalation.warning_count, escalation.period_days));
+
+        let punishment_model = match escalation.punishment_type {
+            PunishmentType::Dunce => punishments::dunce_user(db, ctx, server_id, user_id, None, reason, expires).await?,
+            PunishmentType::Ban => punishments::ban_user(db, ctx, server_id, user_id, None, reason, expires, 0).await?,
+            PunishmentType::Timeout => {
+                let expires = expires.ok_or(RaincoatError { cause: "Timeouts require a duration".to_string() })?;
+                punishments::timeout_user(db, ctx, server_id, user_id, None, reason, expires).await?
+            }
+            PunishmentType::Kick | PunishmentType::Softban => return Err(RaincoatError { cause: "Kicks and softbans are not supported as escalations".to_string() })
+        };
+
+        let mut updated: warning::ActiveModel = warning_model.into();
+        updated.escalated_count = Set(Some(escalation.warning_count));
+        updated.update(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+        return Ok(Some(punishment_model));
+    }
+
+    Ok(None)
+}
+
+pub async fn create_warn_response(db: &DatabaseConnection, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<(), RaincoatError> {
+    let server_id = command.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
+
+    let mut user_id_opt: Option<u64> = None;
+    let mut reason_opt: Option<String> = None;
+
+    for option in &command.data.options {
+        match option.name.as_str() {
+            "user" => {
+                if let ApplicationCommandInteractionDataOptionValue::User(user, _member) = &option.resolved.as_ref()
+                    .ok_or(RaincoatError { cause: "Couldn't resolve 'user' param".to_string() })? {
+                    user_id_opt = Some(user.id.0);
+                } else {
+                    return Err(RaincoatError { cause: "Unexpected type for 'user' param".to_string() })
+                }
+            }
+            "reason" => reason_opt = Some(punishments::parse_string_option("reason", option)?),
+            unknown => {
+                return Err(RaincoatError { cause: format!("Unknown param: {}", unknown)})
+            }
+        }
+    }
+
+    let user_id = user_id_opt.ok_or(RaincoatError { cause: "Requires 'user' param".to_string() })?;
+    let reason = reason_opt.ok_or(RaincoatError { cause: "Requires 'reason' param".to_string() })?;
+
+    let new_warning = warning::ActiveModel {
+        server_id: Set(server_id.0 as i64),
+        user_id: Set(user_id as i64),
+        moderator_id: Set(command.user.id.0 as i64),
+        reason: Set(reason.clone()),
+        created_at: Set(Utc::now().naive_utc()),
+        ..Default::default()
+    };
+    let warning_model: warning::Model = new_warning.insert(db).await
+        .map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+    mod_log::post(db, &ctx.http, server_id, ModLogEntry {
+        action: format!("Warning #{}", warning_model.id),
+        target_id: user_id,
+        moderator_id: Some(command.user.id.0),
+        case_number: None,
+        duration: None,
+        reason: Some(reason.clone())
+    }).await;
+
+    let warning_id = warning_model.id;
+    let escalation = match escalate(db, ctx, server_id, user_id, warning_model).await {
+        Ok(Some(punishment_model)) => {
+            let action = match punishment_model.punishment_type {
+                PunishmentType::Dunce => "dunced",
+                PunishmentType::Timeout => "timed out",
+                _ => "banned"
+            };
+            match punishment_model.expires {
+                Some(expires) => format!("\nThey have been automatically {} until <t:{}> (case #{})", action, expires.timestamp(), punishment_model.case_number),
+                None => format!("\nThey have been automatically {} indefinitely (case #{})", action, punishment_model.case_number)
+            }
+        }
+        Ok(None) => String::new(),
+        Err(err) => format!("\nFailed to apply automatic punishment: {}", err.cause)
+    };
+
+    command.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                message.content(format!("Warned <@{}> (warning #{})\nReason: {}{}", user_id, warning_id, reason, escalation))
+                    .allowed_mentions(|f| f.empty_parse())
+            })
+    }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
+}
+
+pub async fn create_warnings_response(db: &DatabaseConnection, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<(), RaincoatError> {
+    let server_id = command.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
+
+    let mut user_id_opt: Option<u64> = None;
+
+    for option in &command.data.options {
+        match option.name.as_str() {
+            "user" => {
+                if let ApplicationCommandInteractionDataOptionValue::User(user, _member) = &option.resolved.as_ref()
+                    .ok_or(RaincoatError { cause: "Couldn't resolve 'user' param".to_string() })? {
+                    user_id_opt = Some(user.id.0);
+                } else {
+                    return Err(RaincoatError { cause: "Unexpected type for 'user' param".to_string() })
+                }
+            }
+            unknown => {
+                return Err(RaincoatError { cause: format!("Unknown param: {}", unknown)})
+            }
+        }
+    }
+
+    let user_id = user_id_opt.ok_or(RaincoatError { cause: "Requires 'user' param".to_string() })?;
+
+    let warnings: Vec<warning::Model> = warning::Entity::find()
+        .filter(warning::Column::ServerId.eq(server_id.0 as i64))
+        .filter(warning::Column::UserId.eq(user_id as i64))
+        .order_by_desc(warning::Column::CreatedAt)
+        .all(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+    command.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                message.create_embed(|e| {
+                    e.title("Warnings");
+                    if warnings.is_empty() {
+                        e.description(format!("<@{}> has no warnings on this server.", user_id));
+                    } else {
+                        e.description(format!("<@{}> has {} warning(s)", user_id, warnings.len()));
+                        // Embeds are limited to 25 fields, so only the most recent warnings are shown
+                        for warning in warnings.iter().take(25) {
+                            e.field(format!("Warning #{}", warning.id),
+                                    format!("Issued <t:{}> by <@{}>\nReason: {}", warning.created_at.timestamp(), warning.moderator_id, warning.reason),
+                                    false);
+                        }
+                    }
+                    e
+                }).allowed_mentions(|f| f.empty_parse())
+            })
+    }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
+}
+
+pub async fn create_clearwarn_response(db: &DatabaseConnection, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<(), RaincoatError> {
+    let server_id = command.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
+
+    let mut warning_id_opt: Option<i64> = None;
+
+    for option in &command.data.options {
+        match option.name.as_str() {
+            "id" => warning_id_opt = Some(punishments::parse_integer_option("id", option)?),
+            unknown => {
+                return Err(RaincoatError { cause: format!("Unknown param: {}", unknown)})
+            }
+        }
+    }
+
+    let warning_id = warning_id_opt.ok_or(RaincoatError { cause: "Requires 'id' param".to_string() })?;
+
+    let warning_model = warning::Entity::find_by_id(warning_id)
+        .filter(warning::Column::ServerId.eq(server_id.0 as i64))
+        .one(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?
+        .ok_or(RaincoatError { cause: format!("No warning #{} exists on this server.", warning_id) })?;
+    let user_id = warning_model.user_id as u64;
+
+    warning_model.delete(db).await
+        .map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+    mod_log::post(db, &ctx.http, server_id, ModLogEntry {
+        action: format!("Warning #{} cleared", warning_id),
+        target_id: user_id,
+        moderator_id: Some(command.user.id.0),
+        case_number: None,
+        duration: None,
+        reason: None
+    }).await;
+
+    command.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                message.content(format!("Removed warning #{} from <@{}>", warning_id, user_id))
+                    .allowed_mentions(|f| f.empty_parse())
+            })
+    }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
+}
+
+pub async fn create_add_escalation_response(db: &DatabaseConnection, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<(), RaincoatError> {
+    let server_id = command.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
+
+    let mut warning_count_opt: Option<i64> = None;
+    let mut period_opt: Option<i64> = None;
+    let mut punishment_type_opt: Option<PunishmentType> = None;
+    let mut time_accumulator: Duration = Duration::zero();
+
+    for option in &command.data.options {
+        match option.name.as_str() {
+            "warnings" => warning_count_opt = Some(punishments::parse_integer_option("warnings", option)?),
+            "period" => period_opt = Some(punishments::parse_integer_option("period", option)?),
+            "punishment" => {
+                punishment_type_opt = Some(match punishments::parse_string_option("punishment", option)?.as_str() {
+                    "dunce" => PunishmentType::Dunce,
+                    "ban" => PunishmentType::Ban,
+                    "timeout" => PunishmentType::Timeout,
+                    unknown => return Err(RaincoatError { cause: format!("Unknown punishment: {}", unknown) })
+                });
+            }
+            other => punishments::duration_parse(&mut time_accumulator, other, option)?
+        }
+    }
+
+    let warning_count = warning_count_opt.ok_or(RaincoatError { cause: "Requires 'warnings' param".to_string() })?;
+    let period = period_opt.ok_or(RaincoatError { cause: "Requires 'period' param".to_string() })?;
+    let punishment_type = punishment_type_opt.ok_or(RaincoatError { cause: "Requires 'punishment' param".to_string() })?;
+    let duration_minutes = if time_accumulator == Duration::zero() {
+        None
+    } else {
+        Some(time_accumulator.num_minutes())
+    };
+    if punishment_type == PunishmentType::Timeout {
+        punishments::check_timeout_duration(duration_minutes.map(Duration::minutes))?;
+    }
+
+    // Two escalations at the same threshold would both fire for the same warning
+    let duplicate = warning_escalation::Entity::find()
+        .filter(warning_escalation::Column::ServerId.eq(server_id.0 as i64))
+        .filter(warning_escalation::Column::WarningCount.eq(warning_count))
+        .one(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+    if let Some(duplicate) = duplicate {
+        return Err(RaincoatError { cause: format!("Escalation #{} already triggers at {} warnings, remove it first with /removeescalation", duplicate.id, warning_count) });
+    }
+
+    let new_escalation = warning_escalation::ActiveModel {
+        server_id: Set(server_id.0 as i64),
+        warning_count: Set(warning_count),
+        period_days: Set(period),
+        punishment_type: Set(punishment_type),
+        duration_minutes: Set(duration_minutes),
+        ..Default::default()
+    };
+    let escalation_model: warning_escalation::Model = new_escalation.insert(db).await
+        .map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+    command.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                message.content(format!("Added escalation #{}: {}", escalation_model.id, escalation_description(&escalation_model)))
+            })
+    }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
+}
+
+pub async fn create_remove_escalation_response(db: &DatabaseConnection, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<(), RaincoatError> {
+    let server_id = command.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
+
+    let mut escalation_id_opt: Option<i64> = None;
+
+    for option in &command.data.options {
+        match option.name.as_str() {
+            "id" => escalation_id_opt = Some(punishments::parse_integer_option("id", option)?),
+            unknown => {
+                return Err(RaincoatError { cause: format!("Unknown param: {}", unknown)})
+            }
+        }
+    }
+
+    let escalation_id = escalation_id_opt.ok_or(RaincoatError { cause: "Requires 'id' param".to_string() })?;
+
+    let escalation_model = warning_escalation::Entity::find_by_id(escalation_id)
+        .filter(warning_escalation::Column::ServerId.eq(server_id.0 as i64))
+        .one(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?
+        .ok_or(RaincoatError { cause: format!("No escalation #{} exists on this server.", escalation_id) })?;
+
+    escalation_model.delete(db).await
+        .map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+    command.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                message.content(format!("Removed escalation #{}", escalation_id))
+            })
+    }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
+}
+
+pub async fn create_escalations_response(db: &DatabaseConnection, ctx: &Context, command: &ApplicationCommandInteraction) -> Result<(), RaincoatError> {
+    let server_id = command.guild_id.ok_or(RaincoatError { cause: "This command can only be run in servers.".to_string() })?;
+
+    let escalations: Vec<warning_escalation::Model> = warning_escalation::Entity::find()
+        .filter(warning_escalation::Column::ServerId.eq(server_id.0 as i64))
+        .order_by_asc(warning_escalation::Column::WarningCount)
+        .all(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+    let content = if escalations.is_empty() {
+        "No escalations are configured for this server.".to_string()
+    } else {
+        escalations.iter()
+            .map(|escalation| format!("#{}: {}", escalation.id, escalation_description(escalation)))
+            .collect::<Vec<String>>()
+            .join("\n")
+    };
+
+    command.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                message.content(content)
+            })
+    }).await.map_err(|err| RaincoatError { cause: format!("Failed to send interaction response: {}", err) })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::punishment::PunishmentType;
+    use crate::model::warning_escalation;
+    use super::reaches_threshold;
+
+    #[test]
+    fn only_the_exact_count_reaches_a_threshold() {
+        let escalation = warning_escalation::Model {
+            id: 1,
+            server_id: 1,
+            warning_count: 3,
+            period_days: 30,
+            punishment_type: PunishmentType::Timeout,
+            duration_minutes: Some(60)
+        };
+
+        assert!(!reaches_threshold(&escalation, 2, None));
+        assert!(reaches_threshold(&escalation, 3, None));
+        assert!(!reaches_threshold(&escalation, 4, None));
+    }
+
+    #[test]
+    fn handled_thresholds_do_not_fire_again() {
+        let escalation = warning_escalation::Model {
+            id: 1,
+            server_id: 1,
+            warning_count: 3,
+            period_days: 30,
+            punishment_type: PunishmentType::Dunce,
+            duration_minutes: None
+        };
+
+        assert!(!reaches_threshold(&escalation, 3, Some(3)));
+        assert!(!reaches_threshold(&escalation, 3, Some(5)));
+        assert!(reaches_threshold(&escalation, 3, Some(2)));
+    }
+}
@@ -12,3 +12,12 @@ impl Display for RaincoatError {
         write!(f, "cause: {}", self.cause)
     }
 }
+
+/// Whether a Discord request failed because what it referred to no longer exists.
+pub fn is_not_found(err: &serenity::Error) -> bool {
+    match err {
+        serenity::Error::Http(http_err) => matches!(http_err.as_ref(),
+            serenity::http::HttpError::UnsuccessfulRequest(response) if response.status_code.as_u16() == 404),
+        _ => false
+    }
+}
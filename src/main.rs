@@ -2,127 +2,397 @@ mod config;
 mod commands;
 mod model;
 mod error;
+mod mod_log;
+mod scheduler;
 
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use chrono::{Utc, Duration};
+use std::sync::atomic::{AtomicBool, Ordering};
+use chrono::{DateTime, NaiveDateTime, Utc, Duration};
 use sqlx::postgres::PgPoolOptions;
-use sea_orm::{DatabaseConnection, SqlxPostgresConnector, EntityTrait, QueryFilter, ColumnTrait};
 use sea_orm::ActiveValue::Set;
+use sea_orm::{DatabaseConnection, SqlxPostgresConnector, EntityTrait, QueryFilter, ColumnTrait, ModelTrait, ActiveModelTrait};
 
 use serenity::async_trait;
-use serenity::builder::CreateApplicationCommands;
 use serenity::cache::Cache;
 use serenity::client::bridge::gateway::GatewayIntents;
 use serenity::http::Http;
-use serenity::model::channel::{Reaction, ReactionType};
+use serenity::model::channel::{ChannelType, Reaction, ReactionType};
 use serenity::model::gateway::Ready;
-use serenity::model::guild::{Guild, Member};
-use serenity::model::id::{GuildId, RoleId};
+use serenity::model::guild::{Guild, Member, Role};
+use serenity::builder::CreateComponents;
+use serenity::model::id::{GuildId, RoleId, UserId};
+use serenity::model::user::User;
 use serenity::model::interactions::{Interaction, InteractionResponseType};
 use serenity::model::interactions::application_command::ApplicationCommand;
 use serenity::model::prelude::InteractionApplicationCommandCallbackDataFlags;
 use serenity::prelude::*;
 
 use crate::error::RaincoatError;
+use crate::mod_log::ModLogEntry;
 use crate::model::server;
+use crate::model::captcha_challenge;
 use crate::model::punishment;
 use crate::model::punishment_removed_role;
+use crate::model::verification_reminder;
+use crate::model::verification_request;
 use crate::punishment::PunishmentType;
+use crate::commands::{parse_reminder_percents, VerificationOutcome};
+use crate::scheduler::Scheduler;
+
+/// Stands in for a reminder percentage to record that a user was sent the notice that they are being kicked.
+const KICK_NOTICE_PERCENT: i64 = 100;
 
 struct RaincoatCatEventHandler {
-    db: Arc<DatabaseConnection>
+    db: Arc<DatabaseConnection>,
+    // `cache_ready` can fire again after a reconnect, so the background tasks are only started the first time
+    background_started: AtomicBool
 }
 
 impl RaincoatCatEventHandler {
     async fn update_server(server: Guild, server_model: server::Model, db: Arc<DatabaseConnection>, http: Arc<Http>) -> Result<(), RaincoatError> {
         // Iterate through users and determine if any should be kicked due to the verification timeout
         if let (Some(verification_timeout), Some(verified_role_id)) = (server_model.verification_timeout, server_model.verified_role_id) {
+            // Users with an active punishment are being dealt with already
+            let punished_users: HashSet<i64> = punishment::Entity::find()
+                .filter(punishment::Column::ServerId.eq(server.id.0 as i64))
+                .filter(punishment::Column::ResolvedAt.is_null())
+                .all(db.as_ref()).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?
+                .into_iter()
+                .map(|punishment| punishment.user_id)
+                .collect();
+
+            // Users waiting on a moderator's approval have done everything asked of them
+            let pending_users: HashSet<i64> = verification_request::Entity::find()
+                .filter(verification_request::Column::ServerId.eq(server.id.0 as i64))
+                .all(db.as_ref()).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?
+                .into_iter()
+                .map(|request| request.user_id)
+                .collect();
+
+            let mut reminders: HashMap<i64, verification_reminder::Model> = verification_reminder::Entity::find()
+                .filter(verification_reminder::Column::ServerId.eq(server.id.0 as i64))
+                .all(db.as_ref()).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?
+                .into_iter()
+                .map(|reminder| (reminder.user_id, reminder))
+                .collect();
+
+            let mut reminder_percents: Vec<i64> = server_model.verification_reminder_percents.as_deref()
+                .map(parse_reminder_percents)
+                .unwrap_or_default();
+            reminder_percents.sort_unstable();
+
             for (user_id, member) in &server.members {
                 if !member.roles.contains(&RoleId(verified_role_id as u64)) && !member.user.bot {
                     if let Some(joined_at) = member.joined_at {
-                        if joined_at < Utc::now() - Duration::hours(verification_timeout) {
-
-                            // Check if the user has an active punishment.
-                            let punishments = punishment::Entity::find()
-                                .filter(punishment::Column::UserId.eq(user_id.0 as i64))
-                                .filter(punishment::Column::ServerId.eq(server.id.0 as i64))
-                                .all(db.as_ref()).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
-
-                            // Don't kick user if they're otherwise being punished right now
-                            if punishments.len() == 0 {
-                                println!("Kicking user {} from server {} for failing to verify within {} hours.", user_id.0, server.name, verification_timeout);
-                                server.kick(&http, user_id).await
-                                    .map_err(|err| RaincoatError { cause: format!("Failed to kick user {} from server {}: {}", user_id.0, server.name, err) })?;
+                        let reminder = reminders.remove(&(user_id.0 as i64));
+                        let last_percent = reminder.as_ref()
+                            .filter(|reminder| reminder.joined_at == joined_at.naive_utc())
+                            .map(|reminder| reminder.last_percent);
+
+                        // Don't kick user if they're otherwise being punished right now
+                        if punished_users.contains(&(user_id.0 as i64)) || pending_users.contains(&(user_id.0 as i64)) {
+                            continue;
+                        }
+
+                        let kick_at = joined_at + Duration::hours(verification_timeout);
+
+                        if kick_at < Utc::now() {
+                            println!("Kicking user {} from server {} for failing to verify within {} hours.", user_id.0, server.name, verification_timeout);
+
+                            // The DM has to go out before the kick, since we can no longer message users who share no servers with us.
+                            // It is recorded as a reminder, so a kick that fails and is retried later doesn't DM the user again.
+                            if last_percent.is_none_or(|last_percent| last_percent < KICK_NOTICE_PERCENT) {
+                                let mut kick_message = format!("You have been kicked from {} for not verifying within {} hours.", server.name, verification_timeout);
+                                if let Some(invite_url) = &server_model.verification_invite_url {
+                                    kick_message.push_str(&format!(" You can rejoin at any time with {}", invite_url));
+                                }
+                                if let Err(err) = member.user.direct_message(&http, |m| m.content(kick_message)).await {
+                                    eprintln!("Failed to DM user {} before kicking: {}", user_id.0, err);
+                                }
+
+                                Self::record_reminder(db.as_ref(), server.id, *user_id, joined_at.naive_utc(), KICK_NOTICE_PERCENT, reminder.is_some()).await?;
                             }
+
+                            if let Err(err) = server.kick(&http, user_id).await {
+                                eprintln!("Failed to kick user {} from server {}: {}", user_id.0, server.name, err);
+                                continue;
+                            }
+
+                            mod_log::post(db.as_ref(), &http, server.id, ModLogEntry {
+                                action: "Verification timeout kick".to_string(),
+                                target_id: user_id.0,
+                                moderator_id: None,
+                                case_number: None,
+                                duration: None,
+                                reason: Some(format!("Failed to verify within {} hours", verification_timeout))
+                            }).await;
+
+                            verification_reminder::Entity::delete_many()
+                                .filter(verification_reminder::Column::ServerId.eq(server.id.0 as i64))
+                                .filter(verification_reminder::Column::UserId.eq(user_id.0 as i64))
+                                .exec(db.as_ref()).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+                            continue;
+                        }
+
+                        // Only the latest reminder that is due gets sent, so a user isn't sent several at once after downtime
+                        let elapsed_minutes = (Utc::now() - joined_at).num_minutes();
+                        let due_percent = reminder_percents.iter()
+                            .rev()
+                            .find(|percent| elapsed_minutes * 100 >= verification_timeout * 60 * **percent);
+                        if let Some(due_percent) = due_percent {
+                            if last_percent.is_some_and(|last_percent| last_percent >= *due_percent) {
+                                continue;
+                            }
+
+                            // Only a reminder that actually reached the user counts, so a failed one is tried again next time
+                            if let Err(err) = member.user.direct_message(&http, |m| {
+                                m.content(format!("Reminder: you haven't verified in {} yet. If you don't verify <t:{}:R>, you will be kicked.", server.name, kick_at.timestamp()))
+                            }).await {
+                                eprintln!("Failed to send verification reminder to user {}: {}", user_id.0, err);
+                                continue;
+                            }
+
+                            Self::record_reminder(db.as_ref(), server.id, *user_id, joined_at.naive_utc(), *due_percent, reminder.is_some()).await?;
                         }
                     }
                 }
             }
+
+            // Whatever is left belongs to users who have verified or left, so it is no longer needed
+            for reminder in reminders.into_values() {
+                reminder.delete(db.as_ref()).await
+                    .map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+            }
         }
 
-        // Undo punishments that have now expired
-        let punishments: Vec<(punishment::Model, Vec<punishment_removed_role::Model>)> = punishment::Entity::find()
-            .filter(punishment::Column::ServerId.eq(server.id.0 as i64))
-            .filter(punishment::Column::Expires.lt(Utc::now().naive_utc()))
-            .find_with_related(punishment_removed_role::Entity)
-            .all(db.as_ref()).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+        Ok(())
+    }
+
+    /// Remembers the last message sent to a user who has yet to verify, so it isn't sent again.
+    async fn record_reminder(db: &DatabaseConnection, server_id: GuildId, user_id: UserId, joined_at: NaiveDateTime, percent: i64, exists: bool) -> Result<(), RaincoatError> {
+        let sent = verification_reminder::ActiveModel {
+            server_id: Set(server_id.0 as i64),
+            user_id: Set(user_id.0 as i64),
+            joined_at: Set(joined_at),
+            last_percent: Set(percent)
+        };
+        if exists {
+            sent.update(db).await
+        } else {
+            sent.insert(db).await
+        }.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+        Ok(())
+    }
+
+    /// Verifies a user who reacted to the verification message.
+    ///
+    /// There is nowhere to reply to a reaction, so anything the user needs to see goes out by DM.
+    async fn verify_by_reaction(ctx: &Context, db: &DatabaseConnection, server_model: &server::Model, user_id: UserId) {
+        let (content, components) = match commands::begin_verification(db, ctx, server_model, user_id).await {
+            Ok(VerificationOutcome::Verified | VerificationOutcome::AlreadyVerified) => return,
+            Ok(VerificationOutcome::Challenged(content, components)) => (content, components),
+            Ok(outcome) => (outcome.message().to_string(), CreateComponents::default()),
+            Err(err) => {
+                eprintln!("{}", err);
+                return
+            }
+        };
+
+        match user_id.create_dm_channel(&ctx.http).await {
+            Ok(dm_channel) => {
+                if let Err(err) = dm_channel.send_message(&ctx.http, |m| m.content(content).set_components(components)).await {
+                    eprintln!("Failed to send verification message to user {}: {}", user_id.0, err);
+                }
+            }
+            Err(err) => eprintln!("Failed to open DM with user {}: {}", user_id.0, err)
+        }
+    }
+
+    /// Verifies anyone who reacted to the verification message while we were offline.
+    async fn resync_verification(ctx: &Context, db: &DatabaseConnection, server_model: &server::Model) -> Result<(), RaincoatError> {
+        let server_id = GuildId(server_model.id as u64);
+        let (message_id, verification_emoji) = match (server_model.verified_role_id, server_model.verification_message_id, &server_model.verification_emoji) {
+            (Some(_), Some(message_id), Some(verification_emoji)) => (message_id, verification_emoji),
+            _ => return Ok(())
+        };
+        // Servers set up before the channel was stored have to have it looked up once
+        let channel_id = match server_model.verification_channel_id {
+            Some(channel_id) => channel_id,
+            None => match Self::find_verification_channel(ctx, db, server_id, message_id as u64).await? {
+                Some(channel_id) => channel_id,
+                None => {
+                    eprintln!("Skipping verification resync for server {}: couldn't find the verification message, so /verification enable must be run again.", server_id.0);
+                    return Ok(())
+                }
+            }
+        };
+        let reaction_type: ReactionType = verification_emoji.clone().try_into()
+            .map_err(|_err| RaincoatError { cause: format!("Server {} has invalid emoji string: {}", server_id.0, verification_emoji) })?;
+
+        // Users waiting on a moderator's approval have already been dealt with
+        let pending_users: HashSet<i64> = verification_request::Entity::find()
+            .filter(verification_request::Column::ServerId.eq(server_id.0 as i64))
+            .all(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?
+            .into_iter()
+            .map(|request| request.user_id)
+            .collect();
+
+        // Users part way through a captcha already know how to continue
+        let challenged_users: HashSet<i64> = captcha_challenge::Entity::find()
+            .filter(captcha_challenge::Column::ServerId.eq(server_id.0 as i64))
+            .all(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?
+            .into_iter()
+            .map(|challenge| challenge.user_id)
+            .collect();
+
+        let mut after = None;
+        loop {
+            let users = ctx.http.get_reaction_users(channel_id as u64, message_id as u64, &reaction_type, 100, after).await
+                .map_err(|err| RaincoatError { cause: format!("Failed to fetch verification reactions in server {}: {}", server_id.0, err) })?;
+
+            for user in &users {
+                if user.bot || pending_users.contains(&(user.id.0 as i64)) || challenged_users.contains(&(user.id.0 as i64)) {
+                    continue;
+                }
+                // Accounts that are still too new would only be sent a refusal
+                if server_model.min_account_age_hours.is_some_and(|min_account_age_hours| user.id.created_at() + Duration::hours(min_account_age_hours) > Utc::now()) {
+                    continue;
+                }
+                // Users who have since left can't be verified
+                if ctx.cache.member(server_id, user.id).await.is_some() {
+                    Self::verify_by_reaction(ctx, db, server_model, user.id).await;
+                }
+            }
 
-        for (punishment, roles) in &punishments {
-            let punishment_delete = punishment::ActiveModel {
-                id: Set(punishment.id),
+            if users.len() < 100 {
+                return Ok(());
+            }
+            after = users.last().map(|user| user.id.0);
+        }
+    }
+
+    /// Searches the server's text channels for the verification message, remembering where it was found.
+    async fn find_verification_channel(ctx: &Context, db: &DatabaseConnection, server_id: GuildId, message_id: u64) -> Result<Option<i64>, RaincoatError> {
+        let channels = ctx.cache.guild_channels(server_id).await.unwrap_or_default();
+
+        for channel_id in channels.values().filter(|channel| channel.kind == ChannelType::Text).map(|channel| channel.id) {
+            if ctx.http.get_message(channel_id.0, message_id).await.is_err() {
+                continue;
+            }
+
+            server::ActiveModel {
+                id: Set(server_id.0 as i64),
+                verification_channel_id: Set(Some(channel_id.0 as i64)),
                 ..Default::default()
-            };
-            punishment::Entity::delete(punishment_delete).exec(db.as_ref()).await
-                .map_err(|err| RaincoatError { cause: format!("{}", err)})?;
+            }.update(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
 
-            match punishment.punishment_type {
-                PunishmentType::Dunce => {
-                    for role in roles {
-                        http.add_member_role(server.id.0, punishment.user_id as u64, role.role_id as u64).await
-                            .map_err(|err| RaincoatError { cause: format!("Unable to return user role: {}", err) })?;
-                        let role_delete = punishment_removed_role::ActiveModel {
-                            id: Set(role.id),
-                            ..Default::default()
-                        };
-                        punishment_removed_role::Entity::delete(role_delete).exec(db.as_ref()).await
-                            .map_err(|err| RaincoatError { cause: format!("{}", err) })?;
-                    }
+            return Ok(Some(channel_id.0 as i64));
+        }
 
-                    if let Some(dunce_role_id) = server_model.dunce_role_id {
-                        http.remove_member_role(server.id.0, punishment.user_id as u64, dunce_role_id as u64).await
-                            .map_err(|err| RaincoatError { cause: format!("Unable to remove dunce role: {}", err) })?;
+        Ok(None)
+    }
+
+    /// Kicks a user whose account is younger than the server allows to join at all.
+    async fn kick_new_account(ctx: &Context, db: &DatabaseConnection, server_model: Option<&server::Model>, member: &Member, kick_account_age_hours: i64) -> Result<(), RaincoatError> {
+        let reason = format!("Account is younger than {} hours", kick_account_age_hours);
+
+        // Let the user know why and when they can come back, since they won't share a server with us once they're kicked
+        let server_name = member.guild_id.name(&ctx.cache).await
+            .unwrap_or_else(|| "the server".to_string());
+        let rejoin_at = member.user.id.created_at() + Duration::hours(kick_account_age_hours);
+        let mut kick_message = format!("You have been kicked from {} because your account is younger than {} hours. You can rejoin <t:{}:R>.", server_name, kick_account_age_hours, rejoin_at.timestamp());
+        if let Some(invite_url) = server_model.and_then(|server_model| server_model.verification_invite_url.as_ref()) {
+            kick_message.push_str(&format!(" You can use {} once your account is old enough.", invite_url));
+        }
+        if let Err(err) = member.user.direct_message(&ctx.http, |m| m.content(kick_message)).await {
+            eprintln!("Failed to DM user {} before kicking: {}", member.user.id.0, err);
+        }
+
+        member.kick_with_reason(&ctx.http, &reason).await
+            .map_err(|err| RaincoatError { cause: format!("Failed to kick user {} from server {}: {}", member.user.id.0, member.guild_id.0, err) })?;
+
+        mod_log::post(db, &ctx.http, member.guild_id, ModLogEntry {
+            action: "Account age kick".to_string(),
+            target_id: member.user.id.0,
+            moderator_id: None,
+            case_number: None,
+            duration: None,
+            reason: Some(reason)
+        }).await;
+
+        Ok(())
+    }
+
+    /// Gives a returning user back the roles they held before a ban that has since ended.
+    async fn restore_ban_roles(ctx: &Context, db: &DatabaseConnection, server_model: &server::Model, member: &mut Member) -> Result<(), RaincoatError> {
+        let bans: Vec<(punishment::Model, Vec<punishment_removed_role::Model>)> = punishment::Entity::find()
+            .filter(punishment::Column::UserId.eq(member.user.id.0 as i64))
+            .filter(punishment::Column::ServerId.eq(server_model.id))
+            .filter(punishment::Column::PunishmentType.eq(PunishmentType::Ban))
+            .filter(punishment::Column::ResolvedAt.is_not_null())
+            .find_with_related(punishment_removed_role::Entity)
+            .all(db).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+        let restore_cutoff = (Utc::now() - Duration::hours(server_model.ban_role_restore_hours)).naive_utc();
+        let mut restored_roles: Vec<RoleId> = Vec::new();
+
+        for (ban, roles) in bans {
+            let within_window = ban.resolved_at.is_some_and(|resolved_at| resolved_at > restore_cutoff);
+
+            for role in roles {
+                let role_id = RoleId(role.role_id as u64);
+                if within_window && !restored_roles.contains(&role_id) {
+                    // Roles that have since been deleted, or are managed by an integration, can't be given back
+                    if let Some(cached_role) = ctx.cache.role(member.guild_id, role_id).await {
+                        if !cached_role.managed {
+                            restored_roles.push(role_id);
+                        }
                     }
                 }
-                PunishmentType::Ban => {
-                    for role in roles {
-                        // TODO: figure out some way to automatically readd roles?
-                        let role_delete = punishment_removed_role::ActiveModel {
-                            id: Set(role.id),
-                            ..Default::default()
-                        };
-                        punishment_removed_role::Entity::delete(role_delete).exec(db.as_ref()).await
-                            .map_err(|err| RaincoatError { cause: format!("{}", err) })?;
-                    }
 
-                    http.remove_ban(server.id.0, punishment.user_id as u64).await
-                        .map_err(|err| RaincoatError { cause: format!("Unable to unban user: {}", err) })?;
-                }
+                // Saved roles are only ever restored once, and are discarded once the window has passed
+                role.delete(db).await
+                    .map_err(|err| RaincoatError { cause: format!("{}", err) })?;
             }
         }
 
+        if !restored_roles.is_empty() {
+            member.add_roles(&ctx.http, restored_roles.as_slice()).await
+                .map_err(|err| RaincoatError { cause: format!("Unable to restore roles: {}", err) })?;
+
+            mod_log::post(db, &ctx.http, member.guild_id, ModLogEntry {
+                action: "Roles restored".to_string(),
+                target_id: member.user.id.0,
+                moderator_id: None,
+                case_number: None,
+                duration: None,
+                reason: Some(format!("Rejoined after a ban; restored {} role(s)", restored_roles.len()))
+            }).await;
+        }
+
         Ok(())
     }
 
     async fn kick_listener(db: Arc<DatabaseConnection>, cache: Arc<Cache>, http: Arc<Http>) {
-        let mut interval_timer = tokio::time::interval(std::time::Duration::from_secs(5));
+        // Verification timeouts are measured in hours, so checking once a minute is plenty
+        let mut interval_timer = tokio::time::interval(std::time::Duration::from_secs(60));
         loop {
             interval_timer.tick().await;
 
-            for server_id in cache.guilds().await {
-                let server = cache.guild(server_id).await.unwrap();
-                if let Some(server_model) = server::Entity::find_by_id(server_id.0 as i64).one(db.as_ref()).await
-                    .expect("DB lookup failed") {
+            let server_models: Vec<server::Model> = match server::Entity::find()
+                .filter(server::Column::VerificationTimeout.is_not_null())
+                .all(db.as_ref()).await {
+                Ok(server_models) => server_models,
+                Err(err) => {
+                    eprintln!("Failure during kick handler: {}", err);
+                    continue;
+                }
+            };
 
+            for server_model in server_models {
+                if let Some(server) = cache.guild(server_model.id as u64).await {
                     if let Err(err) = Self::update_server(server, server_model, Arc::clone(&db), Arc::clone(&http)).await {
                         eprintln!("Failure during kick handler: {}", err);
                     }
@@ -151,48 +421,100 @@ impl EventHandler for RaincoatCatEventHandler {
 
                 server.set_application_commands_permissions(&ctx.http, |f| {
                     commands::set_command_permissions(server_model.mod_role_id as u64, f, &cmds)
-                }).await.expect(format!("Could not set application permissions for server {}", server.0).as_str());
+                }).await.unwrap_or_else(|err| panic!("Could not set application permissions for server {}: {}", server.0, err));
+            }
+        }
+
+        let start_background = !self.background_started.swap(true, Ordering::SeqCst);
+        if start_background {
+            if let Some(scheduler) = ctx.data.read().await.get::<Scheduler>() {
+                tokio::spawn(Arc::clone(scheduler).run(Arc::clone(&ctx.http)));
             }
         }
 
-        tokio::spawn(Self::kick_listener(Arc::clone(&self.db), ctx.cache, ctx.http));
+        // Catch up on verifications missed while offline before anyone can be kicked for not verifying
+        for server in &servers {
+            if let Some(server_model) = server::Entity::find_by_id(server.0 as i64).one(self.db.as_ref()).await
+                .expect("DB lookup failed") {
+                if let Err(err) = Self::resync_verification(&ctx, self.db.as_ref(), &server_model).await {
+                    eprintln!("Failed to resync verification for server {}: {}", server.0, err);
+                }
+            }
+        }
+
+        if start_background {
+            tokio::spawn(Self::kick_listener(Arc::clone(&self.db), ctx.cache, ctx.http));
+        }
     }
 
-    async fn guild_member_addition(&self, ctx: Context, server_id: GuildId, new_member: Member) {
-        if let Some(server_model) = server::Entity::find_by_id(server_id.0 as i64).one(self.db.as_ref()).await
-            .expect("DB lookup failed") {
-            if let Some(dunce_role_id) = server_model.dunce_role_id {
-                let punishments: Vec<punishment::Model> = match punishment::Entity::find()
-                    .filter(punishment::Column::UserId.eq(new_member.user.id.0 as i64))
-                    .filter(punishment::Column::ServerId.eq(server_id.0 as i64))
-                    .all(self.db.as_ref()).await {
-                    Ok(punishments) => punishments,
-                    Err(err) => {
-                        eprintln!("{}", err);
-                        return;
-                    }
-                };
+    async fn guild_member_addition(&self, ctx: Context, server_id: GuildId, mut new_member: Member) {
+        let server_model = match server::Entity::find_by_id(server_id.0 as i64).one(self.db.as_ref()).await {
+            Ok(server_model) => server_model,
+            Err(err) => {
+                eprintln!("{}", err);
+                return;
+            }
+        };
 
-                // Repunish user if necessary
-                for punishment in punishments {
-                    match punishment.punishment_type {
-                        PunishmentType::Dunce => {
-                            if let Err(err) = ctx.http.add_member_role(server_id.0, new_member.user.id.0, dunce_role_id as u64).await {
-                                eprintln!("Failed to redunce user: {}", err);
-                            }
+        if let Some(kick_account_age_hours) = server_model.as_ref().and_then(|server_model| server_model.kick_account_age_hours) {
+            if !new_member.user.bot && new_member.user.id.created_at() + Duration::hours(kick_account_age_hours) > Utc::now() {
+                if let Err(err) = Self::kick_new_account(&ctx, self.db.as_ref(), server_model.as_ref(), &new_member, kick_account_age_hours).await {
+                    eprintln!("{}", err);
+                }
+                return;
+            }
+        }
+
+        let punishments: Vec<punishment::Model> = match punishment::Entity::find()
+            .filter(punishment::Column::UserId.eq(new_member.user.id.0 as i64))
+            .filter(punishment::Column::ServerId.eq(server_id.0 as i64))
+            .filter(punishment::Column::ResolvedAt.is_null())
+            .all(self.db.as_ref()).await {
+            Ok(punishments) => punishments,
+            Err(err) => {
+                eprintln!("{}", err);
+                return;
+            }
+        };
+
+        // Repunish user if necessary
+        for punishment in punishments {
+            match punishment.punishment_type {
+                PunishmentType::Dunce => {
+                    if let Some(dunce_role_id) = server_model.as_ref().and_then(|server_model| server_model.dunce_role_id) {
+                        if let Err(err) = ctx.http.add_member_role(server_id.0, new_member.user.id.0, dunce_role_id as u64).await {
+                            eprintln!("Failed to redunce user: {}", err);
                         }
-                        PunishmentType::Ban => {
-                            if let Err(err) = new_member.ban(&ctx.http, 0).await {
-                                eprintln!("Failed to reban user: {}", err);
-                            }
+                    }
+                }
+                PunishmentType::Ban => {
+                    if let Err(err) = new_member.ban(&ctx.http, 0).await {
+                        eprintln!("Failed to reban user: {}", err);
+                    }
+                }
+                PunishmentType::Kick | PunishmentType::Softban => {}
+                PunishmentType::Timeout => {
+                    if let Some(expires) = punishment.expires {
+                        if let Err(err) = new_member.disable_communication_until_datetime(&ctx.http, DateTime::from_utc(expires, Utc)).await {
+                            eprintln!("Failed to re-timeout user: {}", err);
                         }
                     }
                 }
             }
         }
+
+        if let Some(server_model) = server_model {
+            if let Err(err) = Self::restore_ban_roles(&ctx, self.db.as_ref(), &server_model, &mut new_member).await {
+                eprintln!("Failed to restore roles for user {} in server {}: {}", new_member.user.id.0, server_id.0, err);
+            }
+        }
     }
 
     async fn reaction_add(&self, ctx: Context, added_reaction: Reaction) {
+        if let Err(err) = commands::handle_reaction_add(self.db.as_ref(), &ctx, &added_reaction).await {
+            eprintln!("{}", err);
+        }
+
         let server_id = match added_reaction.guild_id {
             Some(id) => id,
             None => return
@@ -201,11 +523,15 @@ impl EventHandler for RaincoatCatEventHandler {
             Some(id) => id,
             None => return
         };
+        // Bots are never verified, including ourselves when we add the verification reaction
+        if user_id == ctx.cache.current_user_id().await || ctx.cache.user(user_id).await.is_some_and(|user| user.bot) {
+            return;
+        }
 
         if let Some(server) = server::Entity::find_by_id(server_id.0 as i64).one(self.db.as_ref()).await
             .expect("DB lookup failed") {
-            if let (Some(verified_role_id), Some(verification_message_id), Some(verification_emoji))
-                    = (server.verified_role_id, server.verification_message_id, server.verification_emoji) {
+            if let (Some(verification_message_id), Some(verification_emoji))
+                    = (server.verification_message_id, &server.verification_emoji) {
                 // This server has verification set up, check if this reaction is a verify attempt
                 let expected_verification_reaction: ReactionType = match verification_emoji.clone().try_into() {
                     Ok(r) => r,
@@ -217,10 +543,58 @@ impl EventHandler for RaincoatCatEventHandler {
 
                 if verification_message_id as u64 == added_reaction.message_id.0
                     && expected_verification_reaction == added_reaction.emoji {
+                    Self::verify_by_reaction(&ctx, self.db.as_ref(), &server, user_id).await;
+                }
+            }
+        }
+    }
 
-                    // This is a verification attempt, give the verified role
-                    if let Err(err) = ctx.http.add_member_role(server_id.0, user_id.0, verified_role_id as u64).await {
-                        eprintln!("Failed to set verified role in server {}: {}", server_id.0, err);
+    async fn guild_role_delete(&self, ctx: Context, server_id: GuildId, removed_role_id: RoleId, _removed_role: Option<Role>) {
+        if let Err(err) = commands::forget_deleted_role(self.db.as_ref(), &ctx, server_id, removed_role_id).await {
+            eprintln!("Failed to clean up deleted role {} in server {}: {}", removed_role_id.0, server_id.0, err);
+        }
+    }
+
+    async fn guild_member_removal(&self, ctx: Context, server_id: GuildId, user: User, _member: Option<Member>) {
+        if let Err(err) = commands::withdraw_request(self.db.as_ref(), &ctx, server_id, user.id).await {
+            eprintln!("Failed to withdraw verification request for user {} in server {}: {}", user.id.0, server_id.0, err);
+        }
+    }
+
+    async fn reaction_remove(&self, ctx: Context, removed_reaction: Reaction) {
+        if let Err(err) = commands::handle_reaction_remove(self.db.as_ref(), &ctx, &removed_reaction).await {
+            eprintln!("{}", err);
+        }
+
+        let server_id = match removed_reaction.guild_id {
+            Some(id) => id,
+            None => return
+        };
+        let user_id = match removed_reaction.user_id {
+            Some(id) => id,
+            None => return
+        };
+
+        if let Some(server) = server::Entity::find_by_id(server_id.0 as i64).one(self.db.as_ref()).await
+            .expect("DB lookup failed") {
+            if !server.verification_revoke_on_unreact {
+                return;
+            }
+
+            if let (Some(verified_role_id), Some(verification_message_id), Some(verification_emoji))
+                    = (server.verified_role_id, server.verification_message_id, &server.verification_emoji) {
+                let expected_verification_reaction: ReactionType = match verification_emoji.clone().try_into() {
+                    Ok(r) => r,
+                    Err(_) => {
+                        eprintln!("Server {} has invalid emoji string: {}", server_id.0, verification_emoji);
+                        return
+                    }
+                };
+
+                if verification_message_id as u64 == removed_reaction.message_id.0
+                    && expected_verification_reaction == removed_reaction.emoji {
+                    if let Err(err) = ctx.http.remove_member_role(server_id.0, user_id.0, verified_role_id as u64).await {
+                        eprintln!("Failed to remove verified role in server {}: {}", server_id.0, err);
                     }
                 }
             }
@@ -281,9 +655,12 @@ async fn main() {
 
     let db: DatabaseConnection = SqlxPostgresConnector::from_sqlx_postgres_pool(pool);
 
+    let db = Arc::new(db);
+
     let mut client = Client::builder(config.discord_bot_token.as_str())
         .intents(GatewayIntents::all())
-        .event_handler(RaincoatCatEventHandler { db: Arc::new(db) })
+        .type_map_insert::<Scheduler>(Arc::new(Scheduler::new(Arc::clone(&db))))
+        .event_handler(RaincoatCatEventHandler { db, background_started: AtomicBool::new(false) })
         .application_id(config.discord_application_id)
         .await
         .expect("Failed to create discord client");
@@ -0,0 +1,136 @@
+use chrono::{Duration, Utc};
+use sea_orm::{DatabaseConnection, EntityTrait};
+use serenity::http::Http;
+use serenity::model::id::{ChannelId, GuildId};
+
+use crate::model::punishment;
+use crate::model::punishment::PunishmentType;
+use crate::model::server;
+
+/// A single moderation action to be reported in a server's mod-log channel.
+pub struct ModLogEntry {
+    pub action: String,
+    pub target_id: u64,
+    pub moderator_id: Option<u64>,
+    pub case_number: Option<i64>,
+    pub duration: Option<String>,
+    pub reason: Option<String>
+}
+
+impl ModLogEntry {
+    pub fn for_punishment(action: &str, punishment: &punishment::Model) -> ModLogEntry {
+        let duration = match (&punishment.punishment_type, punishment.expires) {
+            (PunishmentType::Kick | PunishmentType::Softban, _) => None,
+            (_, Some(expires)) => Some(format!("{} (until <t:{}>)", format_duration(expires - punishment.created_at), expires.timestamp())),
+            (_, None) => Some("Indefinite".to_string())
+        };
+
+        ModLogEntry {
+            action: action.to_string(),
+            target_id: punishment.user_id as u64,
+            moderator_id: punishment.moderator_id.map(|id| id as u64),
+            case_number: Some(punishment.case_number),
+            duration,
+            reason: punishment.reason.clone()
+        }
+    }
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let mut parts = Vec::new();
+
+    let days = duration.num_days();
+    let hours = duration.num_hours() % 24;
+    let minutes = duration.num_minutes() % 60;
+
+    if days > 0 {
+        parts.push(format!("{}d", days));
+    }
+    if hours > 0 {
+        parts.push(format!("{}h", hours));
+    }
+    if minutes > 0 || parts.is_empty() {
+        parts.push(format!("{}m", minutes));
+    }
+
+    parts.join(" ")
+}
+
+async fn mod_log_channel(db: &DatabaseConnection, server_id: GuildId) -> Option<ChannelId> {
+    match server::Entity::find_by_id(server_id.0 as i64).one(db).await {
+        Ok(Some(server_model)) => server_model.mod_log_channel_id.map(|channel_id| ChannelId(channel_id as u64)),
+        Ok(None) => None,
+        Err(err) => {
+            eprintln!("Failed to look up mod-log channel for server {}: {}", server_id.0, err);
+            None
+        }
+    }
+}
+
+/// Posts an entry to the server's mod-log channel, if one is configured.
+///
+/// Failing to log is reported but never fails the moderation action itself.
+pub async fn post(db: &DatabaseConnection, http: &Http, server_id: GuildId, entry: ModLogEntry) {
+    let channel_id = match mod_log_channel(db, server_id).await {
+        Some(channel_id) => channel_id,
+        None => return
+    };
+
+    if let Err(err) = channel_id.send_message(http, |m| {
+        m.embed(|e| {
+            e.title(&entry.action)
+                .field("User", format!("<@{}> ({})", entry.target_id, entry.target_id), true)
+                .field("Moderator", match entry.moderator_id {
+                    Some(moderator_id) => format!("<@{}>", moderator_id),
+                    None => "Automatic".to_string()
+                }, true);
+            if let Some(case_number) = entry.case_number {
+                e.field("Case", format!("#{}", case_number), true);
+            }
+            if let Some(duration) = &entry.duration {
+                e.field("Duration", duration, true);
+            }
+            e.field("Reason", entry.reason.as_deref().unwrap_or("No reason given"), false)
+                .timestamp(Utc::now())
+        }).allowed_mentions(|f| f.empty_parse())
+    }).await {
+        eprintln!("Failed to post to mod-log channel in server {}: {}", server_id.0, err);
+    }
+}
+
+/// Posts a notice about the bot's own configuration, rather than a moderation action, to the server's mod-log channel.
+pub async fn post_notice(db: &DatabaseConnection, http: &Http, server_id: GuildId, title: &str, description: String) {
+    let channel_id = match mod_log_channel(db, server_id).await {
+        Some(channel_id) => channel_id,
+        None => return
+    };
+
+    if let Err(err) = channel_id.send_message(http, |m| {
+        m.embed(|e| {
+            e.title(title)
+                .description(description)
+                .timestamp(Utc::now())
+        }).allowed_mentions(|f| f.empty_parse())
+    }).await {
+        eprintln!("Failed to post to mod-log channel in server {}: {}", server_id.0, err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use super::format_duration;
+
+    #[test]
+    fn formats_each_unit() {
+        assert_eq!(format_duration(Duration::days(1) + Duration::hours(2) + Duration::minutes(3)), "1d 2h 3m");
+        assert_eq!(format_duration(Duration::hours(5)), "5h");
+        assert_eq!(format_duration(Duration::days(2) + Duration::minutes(30)), "2d 30m");
+    }
+
+    #[test]
+    fn formats_short_durations_as_minutes() {
+        assert_eq!(format_duration(Duration::zero()), "0m");
+        assert_eq!(format_duration(Duration::seconds(59)), "0m");
+    }
+}
@@ -2,3 +2,14 @@ pub mod optional_role;
 pub mod server;
 pub mod punishment;
 pub mod punishment_removed_role;
+pub mod warning;
+pub mod warning_escalation;
+pub mod captcha_challenge;
+pub mod verification_request;
+pub mod verification_reminder;
+pub mod rules_question;
+pub mod rules_answer;
+pub mod role_menu;
+pub mod role_group;
+pub mod reaction_role;
+pub mod temporary_role;
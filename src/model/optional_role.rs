@@ -7,7 +7,11 @@ pub struct Model {
     pub role_id: i64,
     pub server_id: i64,
     pub emoji: Option<String>,
-    pub description: Option<String>
+    pub description: Option<String>,
+    pub group_id: Option<i64>,
+    pub required_role_ids: Option<String>, // comma separated, any of which allows picking this role
+    pub excluded_role_ids: Option<String>, // comma separated, any of which blocks picking this role
+    pub duration_minutes: Option<i64> // how long the role is kept after being picked, if it is temporary
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -6,7 +6,13 @@ pub enum PunishmentType {
     #[sea_orm(string_value = "dunce")]
     Dunce,
     #[sea_orm(string_value = "ban")]
-    Ban
+    Ban,
+    #[sea_orm(string_value = "kick")]
+    Kick,
+    #[sea_orm(string_value = "timeout")]
+    Timeout,
+    #[sea_orm(string_value = "softban")]
+    Softban
 }
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
@@ -17,7 +23,17 @@ pub struct Model {
     pub user_id: i64,
     pub server_id: i64,
     pub punishment_type: PunishmentType,
-    pub expires: Option<DateTime>
+    pub expires: Option<DateTime>,
+
+    pub moderator_id: Option<i64>,
+    pub reason: Option<String>,
+    pub created_at: DateTime,
+
+    pub case_number: i64,
+    pub resolved_at: Option<DateTime>,
+    pub resolved_by: Option<i64>,
+    pub resolved_reason: Option<String>,
+    pub expiry_attempts: i64 // failed attempts to undo the punishment once it expired
 }
 
 #[derive(Copy, Clone, Debug, EnumIter)]
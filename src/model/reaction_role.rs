@@ -0,0 +1,30 @@
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "reaction_role_mode")]
+pub enum ReactionRoleMode {
+    #[sea_orm(string_value = "toggle")]
+    Toggle,
+    #[sea_orm(string_value = "add_only")]
+    AddOnly,
+    #[sea_orm(string_value = "unique")]
+    Unique
+}
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "reaction_roles")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub server_id: i64,
+    pub channel_id: i64,
+    pub message_id: i64,
+    pub emoji: String,
+    pub role_id: i64,
+    pub mode: ReactionRoleMode
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,37 @@
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "rules_answers")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub question_id: i64,
+    pub server_id: i64,
+    pub user_id: i64,
+    pub answer: String,
+    pub answered_at: DateTime
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {
+    RulesQuestion
+}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        match self {
+            Self::RulesQuestion => Entity::belongs_to(super::rules_question::Entity)
+                .from(Column::QuestionId)
+                .to(super::rules_question::Column::Id)
+                .into(),
+        }
+    }
+}
+
+impl Related<super::rules_question::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::RulesQuestion.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,32 @@
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "rules_questions")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub server_id: i64,
+    pub prompt: String,
+    pub free_text: bool // answered with /answer rather than a checkbox
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {
+    RulesAnswer
+}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        match self {
+            Self::RulesAnswer => Entity::has_many(super::rules_answer::Entity).into(),
+        }
+    }
+}
+
+impl Related<super::rules_answer::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::RulesAnswer.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -8,11 +8,36 @@ pub struct Model {
     pub mod_role_id: i64,
 
     pub verified_role_id: Option<i64>,
+    pub verification_channel_id: Option<i64>,
     pub verification_message_id: Option<i64>,
     pub verification_emoji: Option<String>,
+    pub verification_revoke_on_unreact: bool,
     pub verification_timeout: Option<i64>, // in hours
+    pub verification_panel_title: Option<String>,
+    pub verification_panel_text: Option<String>,
+    pub verification_button_label: Option<String>,
+    pub verification_reminder_percents: Option<String>, // comma separated percentages of the timeout
+    pub verification_invite_url: Option<String>,
 
-    pub dunce_role_id: Option<i64>
+    pub captcha_enabled: bool,
+    pub captcha_attempts: i64,
+    pub captcha_lockout_minutes: i64,
+
+    pub min_account_age_hours: Option<i64>,
+    pub account_age_message: Option<String>,
+    pub kick_account_age_hours: Option<i64>,
+
+    pub approval_channel_id: Option<i64>,
+    pub approval_required: bool,
+
+    pub rules_text: Option<String>,
+    pub rules_required: bool,
+
+    pub dunce_role_id: Option<i64>,
+    pub ban_role_restore_hours: i64,
+    pub last_case_number: i64,
+
+    pub mod_log_channel_id: Option<i64>
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -0,0 +1,19 @@
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "warnings")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub server_id: i64,
+    pub user_id: i64,
+    pub moderator_id: i64,
+    pub reason: String,
+    pub created_at: DateTime,
+    pub escalated_count: Option<i64> // the escalation threshold this warning triggered, if any
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,20 @@
+use sea_orm::entity::prelude::*;
+
+use super::punishment::PunishmentType;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "warning_escalations")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub server_id: i64,
+    pub warning_count: i64,
+    pub period_days: i64,
+    pub punishment_type: PunishmentType,
+    pub duration_minutes: Option<i64> // indefinite when absent
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,307 @@
+use std::sync::Arc;
+use chrono::{Duration, Utc};
+use sea_orm::{DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, ColumnTrait, ModelTrait};
+use sea_orm::sea_query::Expr;
+use serenity::http::Http;
+use serenity::model::id::GuildId;
+use serenity::prelude::{Context, TypeMapKey};
+use tokio::sync::Notify;
+
+use crate::error::{is_not_found, RaincoatError};
+use crate::mod_log;
+use crate::mod_log::ModLogEntry;
+use crate::model::punishment;
+use crate::model::punishment::PunishmentType;
+use crate::model::punishment_removed_role;
+use crate::model::server;
+use crate::model::temporary_role;
+
+/// Undoes punishments and removes temporary roles once they expire.
+///
+/// Rather than polling, the scheduler sleeps until the next expiry recorded in the database. Anything that adds or
+/// lifts a timed punishment, or hands out a temporary role, should call [`wake`] so the scheduler can recompute when
+/// it next needs to run.
+pub struct Scheduler {
+    db: Arc<DatabaseConnection>,
+    wakeup: Notify
+}
+
+/// How long to wait before retrying expirations that failed to be undone.
+const RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// How many times an expired punishment is tried before it is given up on and left for a moderator to undo.
+const MAX_EXPIRY_ATTEMPTS: i64 = 10;
+
+/// The longest the scheduler sleeps between purges of roles saved from bans that can no longer be restored.
+const PURGE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+impl TypeMapKey for Scheduler {
+    type Value = Arc<Scheduler>;
+}
+
+impl Scheduler {
+    pub fn new(db: Arc<DatabaseConnection>) -> Scheduler {
+        Scheduler {
+            db,
+            wakeup: Notify::new()
+        }
+    }
+
+    /// Runs the scheduler until the bot shuts down. This should only be called once.
+    pub async fn run(self: Arc<Self>, http: Arc<Http>) {
+        loop {
+            let mut failed = false;
+            if let Err(err) = self.expire_due(&http).await {
+                eprintln!("Failure while expiring punishments: {}", err);
+                failed = true;
+            }
+            if let Err(err) = self.expire_temporary_roles(&http).await {
+                eprintln!("Failure while expiring temporary roles: {}", err);
+                failed = true;
+            }
+            if let Err(err) = self.purge_saved_ban_roles().await {
+                eprintln!("Failure while purging saved ban roles: {}", err);
+            }
+
+            match self.next_expiry().await {
+                Ok(Some(next_expiry)) => {
+                    let mut delay = (next_expiry - Utc::now().naive_utc()).to_std()
+                        .unwrap_or(std::time::Duration::ZERO);
+                    // Whatever failed is still due, so back off instead of retrying straight away
+                    if failed {
+                        delay = delay.max(RETRY_DELAY);
+                    }
+                    delay = delay.min(PURGE_INTERVAL);
+                    tokio::select! {
+                        _ = tokio::time::sleep(delay) => {}
+                        _ = self.wakeup.notified() => {}
+                    }
+                }
+                Ok(None) => {
+                    tokio::select! {
+                        _ = tokio::time::sleep(PURGE_INTERVAL) => {}
+                        _ = self.wakeup.notified() => {}
+                    }
+                }
+                Err(err) => {
+                    eprintln!("Failed to find the next expiry: {}", err);
+                    tokio::time::sleep(std::time::Duration::from_secs(30)).await;
+                }
+            }
+        }
+    }
+
+    async fn next_expiry(&self) -> Result<Option<chrono::NaiveDateTime>, RaincoatError> {
+        let next = punishment::Entity::find()
+            .filter(punishment::Column::ResolvedAt.is_null())
+            .filter(punishment::Column::Expires.is_not_null())
+            .order_by_asc(punishment::Column::Expires)
+            .one(self.db.as_ref()).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+        let next_role = temporary_role::Entity::find()
+            .order_by_asc(temporary_role::Column::Expires)
+            .one(self.db.as_ref()).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+        let next_punishment_expiry = next.and_then(|punishment| punishment.expires);
+        let next_role_expiry = next_role.map(|role| role.expires);
+        Ok(match (next_punishment_expiry, next_role_expiry) {
+            (Some(punishment_expiry), Some(role_expiry)) => Some(punishment_expiry.min(role_expiry)),
+            (punishment_expiry, role_expiry) => punishment_expiry.or(role_expiry)
+        })
+    }
+
+    async fn expire_temporary_roles(&self, http: &Http) -> Result<(), RaincoatError> {
+        let roles: Vec<temporary_role::Model> = temporary_role::Entity::find()
+            .filter(temporary_role::Column::Expires.lte(Utc::now().naive_utc()))
+            .all(self.db.as_ref()).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+        let mut failures = Vec::new();
+        for role in roles {
+            // Only forget the role once it is gone, or once the member or role no longer exists, so a failed removal is retried
+            if let Err(err) = http.remove_member_role(role.server_id as u64, role.user_id as u64, role.role_id as u64).await {
+                if !is_not_found(&err) {
+                    failures.push(format!("role {} in server {}: {}", role.role_id, role.server_id, err));
+                    continue;
+                }
+            }
+
+            temporary_role::Entity::delete_many()
+                .filter(temporary_role::Column::ServerId.eq(role.server_id))
+                .filter(temporary_role::Column::UserId.eq(role.user_id))
+                .filter(temporary_role::Column::RoleId.eq(role.role_id))
+                .filter(temporary_role::Column::Expires.lte(role.expires))
+                .exec(self.db.as_ref()).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+        }
+
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(RaincoatError { cause: format!("Failed to remove temporary roles:\n- {}", failures.join("\n- ")) })
+        }
+    }
+
+    /// Drops roles saved from bans that ended longer ago than the server's restore window, as they will never be given
+    /// back.
+    async fn purge_saved_ban_roles(&self) -> Result<(), RaincoatError> {
+        let servers: Vec<server::Model> = server::Entity::find()
+            .all(self.db.as_ref()).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+        for server_model in servers {
+            let restore_cutoff = (Utc::now() - Duration::hours(server_model.ban_role_restore_hours)).naive_utc();
+
+            let expired_roles: Vec<punishment_removed_role::Model> = punishment_removed_role::Entity::find()
+                .inner_join(punishment::Entity)
+                .filter(punishment::Column::ServerId.eq(server_model.id))
+                .filter(punishment::Column::PunishmentType.eq(PunishmentType::Ban))
+                .filter(punishment::Column::ResolvedAt.lt(restore_cutoff))
+                .all(self.db.as_ref()).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+            if expired_roles.is_empty() {
+                continue;
+            }
+
+            punishment_removed_role::Entity::delete_many()
+                .filter(punishment_removed_role::Column::Id.is_in(expired_roles.iter().map(|role| role.id)))
+                .exec(self.db.as_ref()).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+        }
+
+        Ok(())
+    }
+
+    async fn expire_due(&self, http: &Http) -> Result<(), RaincoatError> {
+        let punishments: Vec<(punishment::Model, Vec<punishment_removed_role::Model>)> = punishment::Entity::find()
+            .filter(punishment::Column::ResolvedAt.is_null())
+            .filter(punishment::Column::Expires.lte(Utc::now().naive_utc()))
+            .find_with_related(punishment_removed_role::Entity)
+            .all(self.db.as_ref()).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+
+        let mut failures = Vec::new();
+        for (punishment, roles) in punishments {
+            // Claim the punishment before undoing it, so one that was lifted by hand in the meantime is left alone
+            let claimed = punishment::Entity::update_many()
+                .col_expr(punishment::Column::ResolvedAt, Expr::value(Utc::now().naive_utc()))
+                .col_expr(punishment::Column::ResolvedReason, Expr::value("Expired"))
+                .filter(punishment::Column::Id.eq(punishment.id))
+                .filter(punishment::Column::ResolvedAt.is_null())
+                .exec(self.db.as_ref()).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+            if claimed.rows_affected == 0 {
+                continue;
+            }
+
+            let err = match self.undo_punishment(http, &punishment, roles).await {
+                Ok(()) => continue,
+                Err(err) => err
+            };
+
+            // The attempts are kept with the punishment, so a restart doesn't start the count over
+            let attempts = punishment.expiry_attempts + 1;
+            if attempts < MAX_EXPIRY_ATTEMPTS {
+                // Put the punishment back if it couldn't be undone, so it is retried rather than silently left in place
+                punishment::Entity::update_many()
+                    .col_expr(punishment::Column::ResolvedAt, Expr::value(Option::<chrono::NaiveDateTime>::None))
+                    .col_expr(punishment::Column::ResolvedReason, Expr::value(Option::<String>::None))
+                    .col_expr(punishment::Column::ExpiryAttempts, Expr::value(attempts))
+                    .filter(punishment::Column::Id.eq(punishment.id))
+                    .exec(self.db.as_ref()).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+                failures.push(format!("punishment {} (attempt {}): {}", punishment.id, attempts, err));
+            } else {
+                // Give up and leave the punishment resolved, so a moderator can see it has to be undone by hand
+                punishment::Entity::update_many()
+                    .col_expr(punishment::Column::ResolvedReason, Expr::value("Expired, but could not be undone"))
+                    .col_expr(punishment::Column::ExpiryAttempts, Expr::value(attempts))
+                    .filter(punishment::Column::Id.eq(punishment.id))
+                    .exec(self.db.as_ref()).await.map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+                mod_log::post(self.db.as_ref(), http, GuildId(punishment.server_id as u64), ModLogEntry {
+                    action: "Expiry failed".to_string(),
+                    target_id: punishment.user_id as u64,
+                    moderator_id: None,
+                    case_number: Some(punishment.case_number),
+                    duration: None,
+                    reason: Some(format!("Gave up after {} attempts, this has to be undone by hand: {}", attempts, err.cause))
+                }).await;
+                failures.push(format!("punishment {} (giving up): {}", punishment.id, err));
+            }
+        }
+
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(RaincoatError { cause: format!("Failed to undo expired punishments:\n- {}", failures.join("\n- ")) })
+        }
+    }
+
+    async fn undo_punishment(&self, http: &Http, punishment: &punishment::Model, roles: Vec<punishment_removed_role::Model>) -> Result<(), RaincoatError> {
+        let server_id = GuildId(punishment.server_id as u64);
+
+        let action = match punishment.punishment_type {
+            PunishmentType::Dunce => {
+                // A member who has left or a role that was deleted can't be given back, so there is nothing left to undo
+                for role in roles {
+                    if let Err(err) = http.add_member_role(server_id.0, punishment.user_id as u64, role.role_id as u64).await {
+                        if !is_not_found(&err) {
+                            return Err(RaincoatError { cause: format!("Unable to return user role: {}", err) });
+                        }
+                    }
+                    role.delete(self.db.as_ref()).await
+                        .map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+                }
+
+                let server_model = server::Entity::find_by_id(server_id.0 as i64).one(self.db.as_ref()).await
+                    .map_err(|err| RaincoatError { cause: format!("{}", err) })?;
+                if let Some(dunce_role_id) = server_model.and_then(|server_model| server_model.dunce_role_id) {
+                    if let Err(err) = http.remove_member_role(server_id.0, punishment.user_id as u64, dunce_role_id as u64).await {
+                        if !is_not_found(&err) {
+                            return Err(RaincoatError { cause: format!("Unable to remove dunce role: {}", err) });
+                        }
+                    }
+                }
+
+                "Dunce expired"
+            }
+            PunishmentType::Ban => {
+                // The saved roles are kept, so they can be restored if the user rejoins. A ban that was already lifted by
+                // hand has nothing left to undo.
+                if let Err(err) = http.remove_ban(server_id.0, punishment.user_id as u64).await {
+                    if !is_not_found(&err) {
+                        return Err(RaincoatError { cause: format!("Unable to unban user: {}", err) });
+                    }
+                }
+
+                "Ban expired"
+            }
+            PunishmentType::Kick => {
+                // Kicks are resolved as soon as they are issued, so there is nothing to undo.
+                "Kick expired"
+            }
+            PunishmentType::Softban => {
+                // Softbans are lifted as soon as they are issued, so there is nothing to undo.
+                "Softban expired"
+            }
+            PunishmentType::Timeout => {
+                // Discord lifts the timeout on its own once it runs out, so it only needs to be marked resolved.
+                "Timeout expired"
+            }
+        };
+
+        mod_log::post(self.db.as_ref(), http, server_id, ModLogEntry {
+            action: action.to_string(),
+            target_id: punishment.user_id as u64,
+            moderator_id: None,
+            case_number: Some(punishment.case_number),
+            duration: None,
+            reason: Some("Expired".to_string())
+        }).await;
+
+        Ok(())
+    }
+
+    pub fn wake(&self) {
+        self.wakeup.notify_one();
+    }
+}
+
+/// Lets the scheduler know that the set of upcoming expirations has changed.
+pub async fn wake(ctx: &Context) {
+    if let Some(scheduler) = ctx.data.read().await.get::<Scheduler>() {
+        scheduler.wake();
+    }
+}